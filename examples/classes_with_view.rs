@@ -0,0 +1,63 @@
+use easy_graph::ui::window::WindowBuilder;
+use kohonen::calc::neighborhood::Neighborhood;
+use kohonen::data::DataFrame;
+use kohonen::map::som::{DecayParam, Layer, Som, SomParams};
+use kohonen::ui::LayerView;
+use rand::prelude::*;
+
+fn main() {
+    let cols = [
+        "x",
+        "y",
+        "side:left",
+        "side:right",
+        "ring:inner",
+        "ring:middle",
+        "ring:outer",
+    ];
+    let params = SomParams::xyf(
+        1000,
+        Neighborhood::Gauss,
+        DecayParam::lin(0.1, 0.01),
+        DecayParam::lin(10.0, 0.6),
+        DecayParam::exp(0.25, 0.0001),
+        vec![
+            Layer::cont(2, 0.5),
+            Layer::cat(2, 0.25),
+            Layer::cat(3, 0.25),
+        ],
+    );
+    let mut som = Som::new(&cols, 16, 16, params);
+
+    let mut rng = rand::thread_rng();
+    let mut data = DataFrame::empty(&cols);
+
+    for _i in 0..5000 {
+        let x: f64 = rng.gen_range(0.0, 1.0);
+        let y: f64 = rng.gen_range(0.0, 1.0);
+
+        let left = if x < 0.5 { 1.0 } else { 0.0 };
+        let dist = ((x - 0.5).powi(2) + (y - 0.5).powi(2)).sqrt();
+        let ring = if dist < 0.2 {
+            [1.0, 0.0, 0.0]
+        } else if dist < 0.4 {
+            [0.0, 1.0, 0.0]
+        } else {
+            [0.0, 0.0, 1.0]
+        };
+        data.push_row(&[x, y, left, 1.0 - left, ring[0], ring[1], ring[2]]);
+    }
+
+    let win = WindowBuilder::new()
+        .with_dimensions(900, 450)
+        .with_fps_skip(2.0)
+        .build();
+
+    // Both selected layers are categorical: one class map per layer
+    let mut viewer = LayerView::new(win, &[1, 2], &cols, None);
+
+    while viewer.is_open() {
+        som.epoch(&data, None);
+        viewer.draw(&som, None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Compares timings of per-column operations on a wide data frame, in row-major and column-major layout.
+//! `DataFrame` statistics like `ranges` and `means` transpose internally; strided row-major loops are shown for reference.
+//!
+//! Run in release mode: `cargo run --release --example column_major_timing`
+use kohonen::calc::norm::{normalize, Norm};
+use kohonen::data::DataFrame;
+use rand::prelude::*;
+use std::time::Instant;
+
+fn main() {
+    let nrows = 2_000;
+    let ncols = 2_000;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let names: Vec<_> = (0..ncols).map(|c| format!("C{}", c)).collect();
+    let mut df = DataFrame::empty(&names.iter().map(|n| &**n).collect::<Vec<_>>());
+    for _ in 0..nrows {
+        df.push_row_iter((0..ncols).map(|_| rng.gen_range(0.0, 1.0)));
+    }
+
+    let start = Instant::now();
+    let columns = df.to_column_major();
+    println!("Transpose:               {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let ranges_strided: Vec<_> = (0..ncols)
+        .map(|col| {
+            df.iter_rows()
+                .fold((std::f64::MAX, std::f64::MIN), |(min, max), row| {
+                    (min.min(row[col]), max.max(row[col]))
+                })
+        })
+        .collect();
+    println!("Ranges, strided:         {:?}", start.elapsed());
+    let start = Instant::now();
+    let ranges = df.ranges();
+    println!("Ranges, with transpose:  {:?}", start.elapsed());
+    let start = Instant::now();
+    let ranges_cm = columns.ranges();
+    println!("Ranges, column-major:    {:?}", start.elapsed());
+    assert_eq!(ranges, ranges_cm);
+    assert_eq!(ranges, ranges_strided);
+
+    let start = Instant::now();
+    let means_strided: Vec<_> = (0..ncols)
+        .map(|col| df.iter_rows().map(|row| row[col]).sum::<f64>() / nrows as f64)
+        .collect();
+    println!("Means, strided:          {:?}", start.elapsed());
+    let start = Instant::now();
+    let means = df.means();
+    println!("Means, with transpose:   {:?}", start.elapsed());
+    let start = Instant::now();
+    let means_cm = columns.means();
+    println!("Means, column-major:     {:?}", start.elapsed());
+    assert!(means
+        .iter()
+        .zip(&means_cm)
+        .zip(&means_strided)
+        .all(|((a, b), c)| (a - b).abs() < 1e-9 && (a - c).abs() < 1e-9));
+
+    let start = Instant::now();
+    let _ = normalize(&df, &vec![Norm::Gauss; ncols], &vec![1.0; ncols]);
+    println!("Normalize, Gauss:        {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let copied: Vec<_> = (0..ncols).map(|col| df.copy_column(col)).collect();
+    println!("Copy columns, row-major: {:?}", start.elapsed());
+    let start = Instant::now();
+    let copied_cm: Vec<_> = columns.iter_columns().map(|col| col.to_vec()).collect();
+    println!("Copy columns, col-major: {:?}", start.elapsed());
+    assert_eq!(copied, copied_cm);
+}
@@ -23,6 +23,7 @@ fn main() {
         DecayParam::lin(0.2, 0.01),
         DecayParam::lin(8.0, 0.5),
         DecayParam::exp(0.2, 0.001),
+        None,
     );
 
     let win_x = WindowBuilder::new()
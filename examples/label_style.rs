@@ -0,0 +1,63 @@
+use easy_graph::color::style::{RGBColor, WHITE};
+use easy_graph::ui::window::WindowBuilder;
+use kohonen::calc::neighborhood::Neighborhood;
+use kohonen::map::som::DecayParam;
+use kohonen::proc::{InputLayer, ProcessorBuilder};
+use kohonen::ui::LayerView;
+
+fn main() {
+    let layers = vec![
+        InputLayer::cont_simple(&[
+            "child_mort_2010",
+            "birth_p_1000",
+            "log_GNI",
+            "LifeExpectancy",
+            "PopGrowth",
+            "PopUrbanized",
+            "PopGrowthUrb",
+            "AdultLiteracy",
+            "PrimSchool",
+            "Income_low_40",
+            "Income_high_20",
+        ]),
+        InputLayer::cat_simple("continent"),
+    ];
+
+    let proc = ProcessorBuilder::new(
+        &layers,
+        &vec!["Country".to_string(), "code".to_string()],
+        &Some("Country".to_string()),
+        &Some(12),
+        &Some(50),
+    )
+    .with_delimiter(b';')
+    .with_no_data("-")
+    .build_from_file("example_data/countries.csv")
+    .unwrap();
+
+    let mut som = proc.create_som(
+        12,
+        16,
+        1000,
+        Neighborhood::Gauss,
+        DecayParam::lin(0.2, 0.01),
+        DecayParam::lin(6.0, 0.8),
+        DecayParam::exp(0.2, 0.001),
+        None,
+    );
+
+    let win = WindowBuilder::new()
+        .with_dimensions(900, 700)
+        .with_fps_skip(5.0)
+        .build();
+
+    let mut view = LayerView::new(win, &[1], &proc.data().columns_ref_vec(), None)
+        .with_label_style(16, WHITE)
+        .with_heading_style(18, RGBColor(60, 60, 60));
+
+    let label_data = proc.labels().map(|lab| (proc.data(), lab));
+    while view.is_open() {
+        som.epoch(proc.data(), None);
+        view.draw(&som, label_data);
+    }
+}
@@ -0,0 +1,58 @@
+//! Compares timings of nearest neighbor search on a wide single-layer SOM,
+//! using the single-layer fast path and the general per-layer distance calculation.
+//!
+//! Run in release mode: `cargo run --release --example nearest_neighbor_timing`
+use kohonen::calc::nn;
+use kohonen::data::DataFrame;
+use kohonen::map::som::Layer;
+use rand::prelude::*;
+use std::time::Instant;
+
+fn main() {
+    let nunits = 1_000;
+    let ncols = 500;
+    let nsamples = 200;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let names: Vec<_> = (0..ncols).map(|c| format!("C{}", c)).collect();
+    let names: Vec<_> = names.iter().map(|n| &**n).collect();
+    let mut units = DataFrame::empty(&names);
+    for _ in 0..nunits {
+        units.push_row_iter((0..ncols).map(|_| rng.gen_range(0.0, 1.0)));
+    }
+    let mut samples = DataFrame::empty(&names);
+    for _ in 0..nsamples {
+        samples.push_row_iter((0..ncols).map(|_| rng.gen_range(0.0, 1.0)));
+    }
+    let layers = vec![Layer::cont(ncols, 1.0)];
+
+    let start = Instant::now();
+    let fast: Vec<_> = samples
+        .iter_rows()
+        .map(|sample| nn::nearest_neighbor_xyf(sample, &units, &layers))
+        .collect();
+    println!("Single-layer fast path: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let general: Vec<_> = samples
+        .iter_rows()
+        .map(|sample| {
+            let mut min_dist = std::f64::MAX;
+            let mut min_idx = 0;
+            for (idx, unit) in units.iter_rows().enumerate() {
+                let dist = nn::distance_xyf(sample, unit, &layers, min_dist);
+                if dist < min_dist {
+                    min_dist = dist;
+                    min_idx = idx;
+                }
+            }
+            (min_idx, min_dist)
+        })
+        .collect();
+    println!("General per-layer path: {:?}", start.elapsed());
+
+    assert!(fast
+        .iter()
+        .zip(&general)
+        .all(|(a, b)| a.0 == b.0 && (a.1 - b.1).abs() < 1e-9));
+}
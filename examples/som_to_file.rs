@@ -1,6 +1,8 @@
 use kohonen::calc::neighborhood::Neighborhood;
 use kohonen::map::som::DecayParam;
 use kohonen::proc::{InputLayer, ProcessorBuilder};
+use std::fs::File;
+use std::io::Write;
 
 fn main() {
     let layers = vec![
@@ -32,7 +34,7 @@ fn main() {
     .build_from_file("example_data/countries.csv")
     .unwrap();
 
-    let _som = proc.create_som(
+    let mut som = proc.create_som(
         16,
         20,
         1000,
@@ -40,10 +42,13 @@ fn main() {
         DecayParam::lin(0.2, 0.01),
         DecayParam::lin(8.0, 0.5),
         DecayParam::exp(0.2, 0.001),
+        None,
     );
-    /*
-    let serialized = serde_json::to_string(&(som, proc.denorm())).unwrap();
-    let mut file = File::create("test.json").unwrap();
+    while let Some(()) = som.epoch(proc.data(), None) {}
+
+    let serialized = som.save_to_json(proc.denorm(), None).unwrap();
+    let path = std::env::temp_dir().join("kohonen-countries-som.json");
+    let mut file = File::create(&path).unwrap();
     file.write_all(serialized.as_bytes()).unwrap();
-    */
+    println!("SOM written to {:?}", path);
 }
@@ -1,11 +1,8 @@
-use easy_graph::color::style::{BLACK, BLUE, WHITE};
-use easy_graph::ui::drawing::IntoDrawingArea;
-use easy_graph::ui::element::{Circle, PathElement};
 use easy_graph::ui::window::WindowBuilder;
 use kohonen::calc::neighborhood::Neighborhood;
 use kohonen::data::DataFrame;
 use kohonen::map::som::{DecayParam, Layer, Som, SomParams};
-use kohonen::ui::LayerView;
+use kohonen::ui::{LayerView, MeshView};
 use rand::prelude::*;
 use std::time::Instant;
 
@@ -40,12 +37,11 @@ fn run_xyf(graphics: bool) {
     }
 
     let mut window = if graphics {
-        Some(
-            WindowBuilder::new()
-                .with_dimensions(500, 500)
-                .with_fps_skip(2.0)
-                .build(),
-        )
+        let win = WindowBuilder::new()
+            .with_dimensions(500, 500)
+            .with_fps_skip(2.0)
+            .build();
+        Some(MeshView::new(win, (0, 1), &cols).with_ranges((0.0, 1.0), (0.0, 1.0)))
     } else {
         None
     };
@@ -67,34 +63,7 @@ fn run_xyf(graphics: bool) {
 
             viewer.as_mut().unwrap().draw(&som, None);
 
-            win.draw(|b| {
-                let root = b.into_drawing_area();
-                root.fill(&WHITE).unwrap();
-
-                for row in data.iter_rows() {
-                    let pt1 = ((500.0 * row[0]) as i32, (500.0 * row[1]) as i32);
-                    root.draw(&Circle::new(pt1, 1, &BLUE)).unwrap();
-                }
-
-                for row in 0..som.nrows() {
-                    for col in 0..som.ncols() {
-                        let wt1 = som.weights_at(row, col);
-                        let pt1 = ((500.0 * wt1[0]) as i32, (500.0 * wt1[1]) as i32);
-                        if row < som.nrows() - 1 {
-                            let wt2 = som.weights_at(row + 1, col);
-                            let pt2 = ((500.0 * wt2[0]) as i32, (500.0 * wt2[1]) as i32);
-                            root.draw(&PathElement::new(vec![pt1, pt2], &BLACK))
-                                .unwrap();
-                        }
-                        if col < som.ncols() - 1 {
-                            let wt3 = som.weights_at(row, col + 1);
-                            let pt3 = ((500.0 * wt3[0]) as i32, (500.0 * wt3[1]) as i32);
-                            root.draw(&PathElement::new(vec![pt1, pt3], &BLACK))
-                                .unwrap();
-                        }
-                    }
-                }
-            });
+            win.draw(&som, Some(&data));
         }
     } else {
         while let Some(()) = som.epoch(&data, None) {}
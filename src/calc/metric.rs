@@ -1,81 +1,240 @@
 //! Distance metrics.
-/*
-/// Trait for distance metrics.
-pub trait Metric: Sync {
-    /// Calculates the distance / dissimilarity between two vectors.
-    fn distance(&self, from: &[f64], to: &[f64]) -> f64;
-}*/
 
 use crate::ParseEnumError;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Trait for distance metrics. Implement it for user-defined metrics,
+/// see [`Layer::with_custom_metric`](../../map/som/struct.Layer.html#method.with_custom_metric).
+pub trait DistanceMetric: Send + Sync {
+    /// Calculates the distance / dissimilarity between two vectors.
+    fn distance(&self, from: &[f64], to: &[f64]) -> f64;
+    /// Calculates a value that is monotonic in the distance, for nearest-neighbor search.
+    /// Defaults to the squared distance.
+    fn distance_sq(&self, from: &[f64], to: &[f64]) -> f64 {
+        self.distance(from, to).powi(2)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Metric {
     SqEuclidean,
     Euclidean,
     Tanimoto,
+    /// Continuous Tanimoto / Jaccard distance, `1 - a·b / (|a|² + |b|² - a·b)`. Suitable for fractional class membership.
+    TanimotoContinuous,
+    /// Match / no-match distance of level indices: the fraction of columns with different levels, after rounding.
+    /// Used for categorical layers stored as a single level index column.
+    Match,
+    /// Hamming distance of binary columns: the fraction of columns where exactly one of the values is `>= 0.5`.
+    /// Suitable for layers of several independent 0/1 flags (multi-label).
+    ///
+    /// Columns with `NaN` in any of the vectors are not compared. In contrast to a Jaccard-type Tanimoto distance,
+    /// columns where both values are 0 count as matches. With the current implementation of `Tanimoto`,
+    /// which also counts thresholded mismatches over all compared columns, both metrics give the same values.
+    Hamming,
 }
 
 impl Metric {
     pub fn distance(&self, from: &[f64], to: &[f64]) -> f64 {
         assert_eq!(from.len(), to.len());
         match self {
-            Metric::SqEuclidean => {
-                let mut sum = 0.0;
-                for (a, b) in from.iter().zip(to) {
-                    if a.is_nan() || b.is_nan() {
-                    } else {
-                        sum += (*a - *b).powi(2);
-                    }
-                }
-                sum
+            Metric::SqEuclidean => sum_sq(from, to),
+            Metric::Euclidean => sum_sq(from, to).sqrt(),
+            Metric::Tanimoto => binary_mismatch(from, to),
+            Metric::TanimotoContinuous => tanimoto_continuous(from, to),
+            Metric::Match => mismatch(from, to),
+            Metric::Hamming => binary_mismatch(from, to),
+        }
+    }
+    /// Calculates the squared distance. Avoids the square root for Euclidean distance.
+    ///
+    /// As it is monotonic in the distance, it can be used for nearest-neighbor search.
+    pub fn distance_sq(&self, from: &[f64], to: &[f64]) -> f64 {
+        assert_eq!(from.len(), to.len());
+        match self {
+            Metric::SqEuclidean => sum_sq(from, to).powi(2),
+            Metric::Euclidean => sum_sq(from, to),
+            Metric::Tanimoto => binary_mismatch(from, to).powi(2),
+            Metric::TanimotoContinuous => tanimoto_continuous(from, to).powi(2),
+            Metric::Match => mismatch(from, to).powi(2),
+            Metric::Hamming => binary_mismatch(from, to).powi(2),
+        }
+    }
+}
+
+impl DistanceMetric for Metric {
+    fn distance(&self, from: &[f64], to: &[f64]) -> f64 {
+        Metric::distance(self, from, to)
+    }
+    fn distance_sq(&self, from: &[f64], to: &[f64]) -> f64 {
+        Metric::distance_sq(self, from, to)
+    }
+}
+
+/// Euclidean distance with a weight per column, `sqrt(sum((w_i * (a_i - b_i))²))`.
+///
+/// Used for whitening of continuous layers, see [`Layer::with_whitening`](../../map/som/struct.Layer.html#method.with_whitening).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeightedEuclidean {
+    weights: Vec<f64>,
+}
+
+impl WeightedEuclidean {
+    /// Creates a weighted Euclidean metric with the given column weights.
+    pub fn new(weights: &[f64]) -> Self {
+        WeightedEuclidean {
+            weights: weights.to_vec(),
+        }
+    }
+    /// The column weights.
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+}
+
+impl DistanceMetric for WeightedEuclidean {
+    fn distance(&self, from: &[f64], to: &[f64]) -> f64 {
+        self.distance_sq(from, to).sqrt()
+    }
+    fn distance_sq(&self, from: &[f64], to: &[f64]) -> f64 {
+        assert_eq!(from.len(), to.len());
+        assert_eq!(from.len(), self.weights.len());
+        let mut sum = 0.0;
+        for ((a, b), w) in from.iter().zip(to).zip(&self.weights) {
+            if a.is_nan() || b.is_nan() {
+            } else {
+                sum += (w * (*a - *b)).powi(2);
             }
-            Metric::Euclidean => {
-                let mut sum = 0.0;
-                for (a, b) in from.iter().zip(to) {
-                    if a.is_nan() || b.is_nan() {
-                    } else {
-                        sum += (*a - *b).powi(2);
-                    }
-                }
-                sum.sqrt()
+        }
+        sum
+    }
+}
+
+fn sum_sq(from: &[f64], to: &[f64]) -> f64 {
+    let mut sum = 0.0;
+    for (a, b) in from.iter().zip(to) {
+        if a.is_nan() || b.is_nan() {
+        } else {
+            sum += (*a - *b).powi(2);
+        }
+    }
+    sum
+}
+
+/// Fraction of compared columns where exactly one of the values is `>= 0.5`. Columns with `NaN` are not compared.
+/// Used by `Tanimoto` and `Hamming`.
+fn binary_mismatch(from: &[f64], to: &[f64]) -> f64 {
+    let mut counter = 0;
+    let mut sum = 0.0;
+    for (a, b) in from.iter().zip(to) {
+        if a.is_nan() || b.is_nan() {
+        } else {
+            counter += 1;
+            if (*a >= 0.5) != (*b >= 0.5) {
+                sum += 1.0;
             }
-            Metric::Tanimoto => {
-                let mut counter = 0;
-                let mut sum = 0.0;
-
-                for (a, b) in from.iter().zip(to) {
-                    if a.is_nan() || b.is_nan() {
-                    } else {
-                        counter += 1;
-                        if *a >= 0.5 {
-                            if *b < 0.5 {
-                                sum += 1.0
-                            }
-                        } else {
-                            if *b >= 0.5 {
-                                sum += 1.0
-                            }
-                        }
-                    }
-                }
-                sum / counter as f64
+        }
+    }
+    sum / counter as f64
+}
+
+fn tanimoto_continuous(from: &[f64], to: &[f64]) -> f64 {
+    let mut dot = 0.0;
+    let mut sq_from = 0.0;
+    let mut sq_to = 0.0;
+    for (a, b) in from.iter().zip(to) {
+        if a.is_nan() || b.is_nan() {
+        } else {
+            dot += a * b;
+            sq_from += a * a;
+            sq_to += b * b;
+        }
+    }
+    let denom = sq_from + sq_to - dot;
+    if denom == 0.0 {
+        0.0
+    } else {
+        1.0 - dot / denom
+    }
+}
+
+fn mismatch(from: &[f64], to: &[f64]) -> f64 {
+    let mut counter = 0;
+    let mut sum = 0.0;
+    for (a, b) in from.iter().zip(to) {
+        if a.is_nan() || b.is_nan() {
+        } else {
+            counter += 1;
+            if a.round() != b.round() {
+                sum += 1.0;
             }
         }
     }
+    sum / counter as f64
 }
+
 impl FromStr for Metric {
     type Err = ParseEnumError;
     /// Parse a string to a `Metric`.
     ///
-    /// Accepts `"euclidean" | "tanimoto"`.
+    /// Accepts `"euclidean" | "tanimoto" | "tanimoto-continuous" | "match" | "hamming"`.
     fn from_str(str: &str) -> Result<Self, Self::Err> {
         match str {
             "euclidean" => Ok(Metric::Euclidean),
             "tanimoto" => Ok(Metric::Tanimoto),
+            "tanimoto-continuous" => Ok(Metric::TanimotoContinuous),
+            "match" => Ok(Metric::Match),
+            "hamming" => Ok(Metric::Hamming),
+            _ => Err(ParseEnumError(format!(
+                "Not a metric: {}. Must be one of (euclidean|tanimoto|tanimoto-continuous|match|hamming)",
+                str
+            ))),
+        }
+    }
+}
+
+/// Distance metrics between units on the SOM grid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GridMetric {
+    /// Euclidean distance.
+    Euclidean,
+    /// Manhattan / city block distance.
+    Manhattan,
+    /// Chebyshev / chessboard distance. Results in square neighborhoods.
+    Chebyshev,
+}
+
+impl GridMetric {
+    /// Calculates the distance between two grid positions given as (row, col).
+    pub fn distance(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        let dr = (from.0 - to.0).abs();
+        let dc = (from.1 - to.1).abs();
+        match self {
+            GridMetric::Euclidean => (dr * dr + dc * dc).sqrt(),
+            GridMetric::Manhattan => dr + dc,
+            GridMetric::Chebyshev => {
+                if dr > dc {
+                    dr
+                } else {
+                    dc
+                }
+            }
+        }
+    }
+}
+impl FromStr for GridMetric {
+    type Err = ParseEnumError;
+    /// Parse a string to a `GridMetric`.
+    ///
+    /// Accepts `"euclidean" | "manhattan" | "chebyshev"`.
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "euclidean" => Ok(GridMetric::Euclidean),
+            "manhattan" => Ok(GridMetric::Manhattan),
+            "chebyshev" => Ok(GridMetric::Chebyshev),
             _ => Err(ParseEnumError(format!(
-                "Not a metric: {}. Must be one of (euclidean|tanimoto)",
+                "Not a grid metric: {}. Must be one of (euclidean|manhattan|chebyshev)",
                 str
             ))),
         }
@@ -147,7 +306,7 @@ impl Metric for TanimotoMetric {
 
 #[cfg(test)]
 mod test {
-    use crate::calc::metric::Metric;
+    use crate::calc::metric::{GridMetric, Metric};
 
     #[test]
     fn tanimoto() {
@@ -160,6 +319,49 @@ mod test {
         assert_eq!(dist, 2.0 / 3.0);
     }
     #[test]
+    fn tanimoto_continuous() {
+        let a = [0.6, 0.4, 0.0];
+        let b = [0.4, 0.6, 0.0];
+        let thresholded = Metric::Tanimoto.distance(&a, &b);
+        let continuous = Metric::TanimotoContinuous.distance(&a, &b);
+        assert_eq!(thresholded, 2.0 / 3.0);
+        assert!((continuous - (1.0 - 0.48 / 0.56)).abs() < 0.000001);
+        assert!(continuous < thresholded);
+
+        assert_eq!(Metric::TanimotoContinuous.distance(&a, &a), 0.0);
+        let dist = Metric::TanimotoContinuous.distance(&[1.0, 0.0], &[0.0, 1.0]);
+        assert_eq!(dist, 1.0);
+
+        assert!(matches!(
+            "tanimoto-continuous".parse::<Metric>(),
+            Ok(Metric::TanimotoContinuous)
+        ));
+    }
+    #[test]
+    fn match_distance() {
+        let a = [2.0, 0.0];
+        let b = [1.6, 1.0];
+        assert_eq!(Metric::Match.distance(&a, &b), 0.5);
+        assert_eq!(Metric::Match.distance(&a, &[2.0, std::f64::NAN]), 0.0);
+        assert_eq!("match".parse::<Metric>().unwrap(), Metric::Match);
+    }
+    #[test]
+    fn hamming() {
+        let a = [1.0, 0.0, 1.0, 0.0, 1.0];
+        let b = [1.0, 1.0, 0.0, 0.0, 0.6];
+        assert_eq!(Metric::Hamming.distance(&a, &b), 2.0 / 5.0);
+        assert_eq!(Metric::Hamming.distance_sq(&a, &b), (2.0_f64 / 5.0).powi(2));
+        assert_eq!(Metric::Hamming.distance(&a, &a), 0.0);
+
+        let c = [0.0, std::f64::NAN, 0.0, 0.0, 1.0];
+        assert_eq!(Metric::Hamming.distance(&a, &c), 1.0 / 4.0);
+        assert_eq!(
+            Metric::Hamming.distance(&a, &b),
+            Metric::Tanimoto.distance(&a, &b)
+        );
+        assert_eq!("hamming".parse::<Metric>().unwrap(), Metric::Hamming);
+    }
+    #[test]
     fn distance() {
         let a = [0.0, 0.0, 0.0];
         let b = [2.0, 2.0, 2.0];
@@ -168,4 +370,27 @@ mod test {
         let dist = Metric::Euclidean.distance(&a, &b);
         assert_eq!(dist, 12f64.sqrt());
     }
+    #[test]
+    fn distance_sq() {
+        let a = [0.0, 0.0, 1.0];
+        let b = [2.0, 2.0, 0.0];
+        for metric in &[
+            Metric::SqEuclidean,
+            Metric::Euclidean,
+            Metric::Tanimoto,
+            Metric::TanimotoContinuous,
+        ] {
+            let dist = metric.distance(&a, &b);
+            let dist_sq = metric.distance_sq(&a, &b);
+            assert!((dist * dist - dist_sq).abs() < 0.000001);
+        }
+    }
+    #[test]
+    fn grid_distance() {
+        let a = (0.0, 0.0);
+        let b = (2.0, 1.0);
+        assert_eq!(GridMetric::Euclidean.distance(a, b), 5f64.sqrt());
+        assert_eq!(GridMetric::Manhattan.distance(a, b), 3.0);
+        assert_eq!(GridMetric::Chebyshev.distance(a, b), 2.0);
+    }
 }
@@ -28,8 +28,18 @@ impl FromStr for Neighbors {
     }
 }
 
+/// Trait for neighborhood functions / kernels. Implement it for user-defined kernels,
+/// see [`SomParams::with_custom_neighborhood`](../../map/som/struct.SomParams.html#method.with_custom_neighborhood).
+pub trait NeighborhoodFn: Send + Sync {
+    /// Calculates the weight, depending on the distance (in units of the neighborhood radius).
+    fn weight(&self, distance: f64) -> f64;
+    /// Maximum search distance in the SOM, in units of the neighborhood radius.
+    /// Units further away are not updated.
+    fn radius(&self) -> f64;
+}
+
 /// Neighborhood functions / kernels.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Neighborhood {
     Gauss,
     Triangular,
@@ -86,6 +96,14 @@ impl Neighborhood {
         }
     }
 }
+impl NeighborhoodFn for Neighborhood {
+    fn weight(&self, distance: f64) -> f64 {
+        Neighborhood::weight(self, distance)
+    }
+    fn radius(&self) -> f64 {
+        Neighborhood::radius(self)
+    }
+}
 impl FromStr for Neighborhood {
     type Err = ParseEnumError;
 
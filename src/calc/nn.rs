@@ -1,4 +1,7 @@
 //! Nearest-neighbor search.
+//!
+//! Ties are broken deterministically: of several candidates with exactly the same distance,
+//! the one with the smallest index is selected, independent of the order of evaluation.
 
 use crate::data::DataFrame;
 
@@ -12,6 +15,13 @@ const EUCLIDEAN_SQ: Metric = Metric::SqEuclidean;
 #[allow(dead_code)]
 const TANIMOTO: Metric = Metric::Tanimoto;
 
+/// Deterministic tie-break: returns true if candidate `idx` with `dist` is closer than the current minimum,
+/// or equally close with a smaller index.
+#[inline]
+fn is_closer(dist: f64, idx: usize, min_dist: f64, min_idx: usize) -> bool {
+    dist < min_dist || (dist == min_dist && idx < min_idx)
+}
+
 /// Nearest-neighbor by Euclidean distance.
 /// Dimensions with `NA` values are ignored.
 /// # Returns
@@ -23,7 +33,7 @@ pub fn nearest_neighbor(from: &[f64], to: &DataFrame) -> (usize, f64) {
     let mut min_idx: usize = 0;
     for (idx_to, row_to) in to.iter_rows().enumerate() {
         let dist = EUCLIDEAN_SQ.distance(from, row_to);
-        if dist < min_dist {
+        if is_closer(dist, idx_to, min_dist, min_idx) {
             min_dist = dist;
             min_idx = idx_to
         }
@@ -42,7 +52,7 @@ pub fn nearest_neighbor_tanimoto(from: &[f64], to: &DataFrame) -> (usize, f64) {
     let mut min_idx: usize = 0;
     for (idx_to, row_to) in to.iter_rows().enumerate() {
         let dist = TANIMOTO.distance(from, row_to);
-        if dist < min_dist {
+        if is_closer(dist, idx_to, min_dist, min_idx) {
             min_dist = dist;
             min_idx = idx_to
         }
@@ -55,14 +65,56 @@ pub fn nearest_neighbor_tanimoto(from: &[f64], to: &DataFrame) -> (usize, f64) {
 /// # Returns
 /// (index, weighted-distance)
 pub fn nearest_neighbor_xyf(from: &[f64], to: &DataFrame, layers: &[Layer]) -> (usize, f64) {
+    nearest_neighbor_xyf_impl(from, to, layers, None)
+}
+
+/// Nearest-neighbor for XYF-maps, using only the layers where `layers_mask` is `true`.
+/// Masked layers do not contribute to the distance (e.g. for predicting an unknown class layer).
+/// Dimensions with `NA` values are ignored.
+/// # Returns
+/// (index, weighted-distance)
+pub fn nearest_neighbor_xyf_masked(
+    from: &[f64],
+    to: &DataFrame,
+    layers: &[Layer],
+    layers_mask: &[bool],
+) -> (usize, f64) {
+    assert_eq!(layers.len(), layers_mask.len());
+    nearest_neighbor_xyf_impl(from, to, layers, Some(layers_mask))
+}
+
+/// For a single layer, the search uses squared distances and takes the square root only for the result.
+/// This is not possible for multiple layers, as the squared weighted sum of distances is not the sum of squares.
+fn nearest_neighbor_xyf_impl(
+    from: &[f64],
+    to: &DataFrame,
+    layers: &[Layer],
+    layers_mask: Option<&[bool]>,
+) -> (usize, f64) {
     assert_eq!(from.len(), to.ncols());
 
+    if layers.len() == 1 && layers_mask.map_or(true, |mask| mask[0]) {
+        let layer = &layers[0];
+        let mut min_dist = std::f64::MAX;
+        let mut min_idx: usize = 0;
+        for (idx_to, row_to) in to.iter_rows().enumerate() {
+            let dist = layer.distance_metric().distance_sq(from, row_to);
+            // undefined distances (e.g. all-NA rows) count as 0.0, like in distance_xyf
+            let dist = if dist.is_nan() { 0.0 } else { dist };
+            if is_closer(dist, idx_to, min_dist, min_idx) {
+                min_dist = dist;
+                min_idx = idx_to;
+            }
+        }
+        return (min_idx, min_dist.sqrt() * layer.weight());
+    }
+
     let mut min_dist = std::f64::MAX;
     let mut min_idx: usize = 0;
     for (idx_to, row_to) in to.iter_rows().enumerate() {
-        let dist = distance_xyf(from, row_to, layers, min_dist);
+        let dist = distance_xyf_impl(from, row_to, layers, layers_mask, min_dist);
         //let dist = distance_xyf(from, row_to, layers, std::f64::MAX);
-        if dist < min_dist {
+        if is_closer(dist, idx_to, min_dist, min_idx) {
             min_dist = dist;
             min_idx = idx_to;
         }
@@ -73,11 +125,42 @@ pub fn nearest_neighbor_xyf(from: &[f64], to: &DataFrame, layers: &[Layer]) -> (
 /// Calculates distance for potential multi-layered data / SOMs.
 /// Stops calculation and returns `f64::MAX` as soon as `min_so_far` is exceeded (for performance).
 pub fn distance_xyf(from: &[f64], to: &[f64], layers: &[Layer], min_so_far: f64) -> f64 {
+    distance_xyf_impl(from, to, layers, None, min_so_far)
+}
+
+/// Calculates distance for potential multi-layered data / SOMs, using only the layers where `layers_mask` is `true`.
+/// Stops calculation and returns `f64::MAX` as soon as `min_so_far` is exceeded (for performance).
+pub fn distance_xyf_masked(
+    from: &[f64],
+    to: &[f64],
+    layers: &[Layer],
+    layers_mask: &[bool],
+    min_so_far: f64,
+) -> f64 {
+    assert_eq!(layers.len(), layers_mask.len());
+    distance_xyf_impl(from, to, layers, Some(layers_mask), min_so_far)
+}
+
+fn distance_xyf_impl(
+    from: &[f64],
+    to: &[f64],
+    layers: &[Layer],
+    layers_mask: Option<&[bool]>,
+    min_so_far: f64,
+) -> f64 {
     let mut start = 0_usize;
     let mut dist = 0.0;
-    for layer in layers {
+    for (idx, layer) in layers.iter().enumerate() {
         let end = start + layer.ncols();
-        let d = layer.metric().distance(&from[start..end], &to[start..end]);
+        if let Some(mask) = layers_mask {
+            if !mask[idx] {
+                start = end;
+                continue;
+            }
+        }
+        let d = layer
+            .distance_metric()
+            .distance(&from[start..end], &to[start..end]);
         /*
         let d = if layer.categorical() {
             TANIMOTO.distance(&from[start..end], &to[start..end])
@@ -111,7 +194,7 @@ pub fn nearest_neighbors(
         let mut min_idx: usize = 0;
         for (idx_to, row_to) in to.iter_rows().enumerate() {
             let dist = EUCLIDEAN_SQ.distance(row_from, row_to);
-            if dist < min_dist {
+            if is_closer(dist, idx_to, min_dist, min_idx) {
                 min_dist = dist;
                 min_idx = idx_to
             }
@@ -160,7 +243,7 @@ pub fn par_nearest_neighbor(from: &[f64], to: &DataFrame<f64>, num_threads: usiz
         let mut min_idx: usize = 0;
         for _ in 0..num_threads {
             let (idx, dist) = rx.recv().unwrap();
-            if dist < min_dist {
+            if is_closer(dist, idx, min_dist, min_idx) {
                 min_dist = dist;
                 min_idx = idx;
             }
@@ -176,7 +259,7 @@ pub fn nearest_neighbor_slice(from: &[f64], to: &[f64], row_offset: usize) -> (u
     let mut min_idx: usize = 0;
     for (idx_to, row_to) in to.chunks(num_cols).enumerate() {
         let dist = EUCLIDEAN_SQ.distance(from, row_to);
-        if dist < min_dist {
+        if is_closer(dist, idx_to + row_offset, min_dist, min_idx) {
             min_dist = dist;
             min_idx = idx_to + row_offset
         }
@@ -187,6 +270,7 @@ pub fn nearest_neighbor_slice(from: &[f64], to: &[f64], row_offset: usize) -> (u
 
 #[cfg(test)]
 mod test {
+    use crate::calc::metric::{DistanceMetric, Metric};
     use crate::calc::nn;
     use crate::data::DataFrame;
     use crate::map::som::Layer;
@@ -213,6 +297,148 @@ mod test {
         let (_idx, _dist) = nn::nearest_neighbor_xyf(&from, &to, &layers);
     }
 
+    #[test]
+    fn xyf_nn_custom_metric() {
+        struct Manhattan;
+        impl DistanceMetric for Manhattan {
+            fn distance(&self, from: &[f64], to: &[f64]) -> f64 {
+                from.iter().zip(to).map(|(a, b)| (a - b).abs()).sum()
+            }
+        }
+
+        let from = [0.0, 0.0, 1.0];
+        let to = DataFrame::from_rows(
+            &["A", "B", "C"],
+            &[vec![0.9, 0.0, 1.0], vec![0.6, 0.6, 1.0]],
+        );
+
+        let layers = vec![Layer::cont(2, 1.0)];
+        assert_eq!(
+            nn::nearest_neighbor_xyf(&from[..2], &to_2d(&to), &layers).0,
+            1
+        );
+        let layers = vec![Layer::cont(2, 1.0).with_custom_metric(Manhattan)];
+        assert_eq!(
+            nn::nearest_neighbor_xyf(&from[..2], &to_2d(&to), &layers).0,
+            0
+        );
+
+        let layers = vec![Layer::cont(2, 0.5), Layer::cat(1, 0.5)];
+        assert_eq!(nn::nearest_neighbor_xyf(&from, &to, &layers).0, 1);
+        let layers = vec![
+            Layer::cont(2, 0.5).with_custom_metric(Manhattan),
+            Layer::cat(1, 0.5),
+        ];
+        assert_eq!(nn::nearest_neighbor_xyf(&from, &to, &layers).0, 0);
+        assert_eq!(
+            nn::distance_xyf(&from, to.get_row(1), &layers, std::f64::MAX),
+            0.6
+        );
+    }
+
+    fn to_2d(df: &DataFrame) -> DataFrame {
+        let rows: Vec<_> = df.iter_rows().map(|row| row[..2].to_vec()).collect();
+        DataFrame::from_rows(&["A", "B"], &rows)
+    }
+
+    #[test]
+    fn nn_ties() {
+        let mut to = DataFrame::empty(&["A", "B", "C", "D", "E"]);
+        for i in 0..10 {
+            if i == 3 || i == 7 {
+                to.push_row(&[0.5, 0.5, 0.5, 1.0, 0.0]);
+            } else {
+                to.push_row(&[1.0, 1.0, 1.0, 0.0, 1.0]);
+            }
+        }
+        let from = [0.5, 0.5, 0.5, 1.0, 0.0];
+
+        assert_eq!(nn::nearest_neighbor(&from, &to).0, 3);
+        assert_eq!(nn::nearest_neighbor_tanimoto(&from, &to).0, 3);
+        let layers = vec![Layer::cont(3, 0.5), Layer::cat(2, 0.5)];
+        assert_eq!(nn::nearest_neighbor_xyf(&from, &to, &layers).0, 3);
+        let layers = vec![Layer::cont(5, 1.0)];
+        assert_eq!(nn::nearest_neighbor_xyf(&from, &to, &layers).0, 3);
+
+        // all units equal
+        let to = DataFrame::filled(10, &["A", "B", "C", "D", "E"], 0.25);
+        assert_eq!(nn::nearest_neighbor(&from, &to).0, 0);
+        let layers = vec![Layer::cont(3, 0.5), Layer::cat(2, 0.5)];
+        assert_eq!(nn::nearest_neighbor_xyf(&from, &to, &layers).0, 0);
+
+        let mut from_df = DataFrame::empty(&["A", "B", "C", "D", "E"]);
+        from_df.push_row(&from);
+        from_df.push_row(&from);
+        let result = nn::nearest_neighbors(&from_df, &to, vec![(0, 0.0); 2]);
+        assert!(result.iter().all(|(idx, _)| *idx == 0));
+    }
+
+    #[test]
+    fn xyf_nn_single_layer() {
+        let mut rng = rand::thread_rng();
+        let from = [0.5, 0.5, 0.5];
+        let mut to = DataFrame::empty(&["A", "B", "C"]);
+
+        for _i in 0..100 {
+            to.push_row(&[
+                rng.gen_range(0.0, 1.0),
+                rng.gen_range(0.0, 1.0),
+                rng.gen_range(0.0, 1.0),
+            ]);
+        }
+        let layers = vec![Layer::cont(3, 1.0)];
+
+        let (idx, dist) = nn::nearest_neighbor(&from, &to);
+        let (idx_xyf, dist_xyf) = nn::nearest_neighbor_xyf(&from, &to, &layers);
+        assert_eq!(idx, idx_xyf);
+        assert!((dist - dist_xyf).abs() < 0.000001);
+
+        let mut min_dist = std::f64::MAX;
+        let mut min_idx = 0;
+        for (i, row) in to.iter_rows().enumerate() {
+            let d = nn::distance_xyf(&from, row, &layers, std::f64::MAX);
+            if d < min_dist {
+                min_dist = d;
+                min_idx = i;
+            }
+        }
+        assert_eq!(min_idx, idx_xyf);
+    }
+
+    #[test]
+    fn xyf_nn_single_layer_na() {
+        let from = [std::f64::NAN, std::f64::NAN];
+        let to = DataFrame::from_rows(&["C:x", "C:y"], &[vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        for metric in &[Metric::Tanimoto, Metric::Match, Metric::Hamming] {
+            let layers = vec![Layer::new(2, 1.0, true, metric.clone())];
+            let (idx, dist) = nn::nearest_neighbor_xyf(&from, &to, &layers);
+            assert_eq!(idx, 0);
+            assert_eq!(dist, 0.0);
+            assert_eq!(
+                dist,
+                nn::distance_xyf(&from, to.get_row(0), &layers, std::f64::MAX)
+            );
+        }
+    }
+
+    #[test]
+    fn xyf_nn_masked() {
+        let from = [0.0, 0.0, 0.0, 1.0];
+        let to = DataFrame::from_rows(
+            &["A", "B", "C:x", "C:y"],
+            &[vec![0.0, 0.0, 1.0, 0.0], vec![0.1, 0.1, 0.0, 1.0]],
+        );
+        let layers = vec![Layer::cont(2, 0.5), Layer::cat(2, 0.5)];
+
+        let (idx, _dist) = nn::nearest_neighbor_xyf(&from, &to, &layers);
+        assert_eq!(idx, 1);
+
+        let (idx, dist) = nn::nearest_neighbor_xyf_masked(&from, &to, &layers, &[true, false]);
+        assert_eq!(idx, 0);
+        assert_eq!(dist, 0.0);
+    }
+
     #[test]
     fn nn_simple() {
         let mut rng = rand::thread_rng();
@@ -40,6 +40,10 @@ pub struct LinearTransform {
 }
 
 impl LinearTransform {
+    /// Creates a new linear transform `value * scale + offset`.
+    pub fn new(scale: f64, offset: f64) -> Self {
+        LinearTransform { scale, offset }
+    }
     pub fn transform(&self, value: f64) -> f64 {
         value * self.scale + self.offset
     }
@@ -57,80 +61,138 @@ impl LinearTransform {
     }
 }
 
-/// Normalize a data frame, with a [`Norm`](struct.Norm.html) and scale per column.
-/// # Returns
-/// A tuple of: (normalized data frame, vector of [`LinearTransform`](struct.LinearTransform.html) for de-normalization, one per column).
-pub fn normalize(
-    data: &DataFrame,
-    norm: &[Norm],
-    scale: &[f64],
-) -> (DataFrame, Vec<LinearTransform>) {
-    let mut counts = vec![0; data.ncols()];
-    let mut params: Vec<_> = norm
-        .iter()
-        .map(|n| match n {
-            Norm::Unit => (std::f64::MAX, std::f64::MIN),
-            _ => (0.0, 0.0),
-        })
-        .collect();
+/// Incremental normalization statistics, for data that is not available as a whole.
+///
+/// Uses running minimum and maximum for [`Norm::Unit`](enum.Norm.html#variant.Unit),
+/// and Welford's algorithm for [`Norm::Gauss`](enum.Norm.html#variant.Gauss).
+/// The resulting transforms match those of [`normalize`](fn.normalize.html).
+#[derive(Debug, Clone)]
+pub struct NormAccumulator {
+    norm: Vec<Norm>,
+    scale: Vec<f64>,
+    counts: Vec<usize>,
+    params: Vec<(f64, f64)>,
+}
 
-    for row in data.iter_rows() {
+impl NormAccumulator {
+    /// Creates a new accumulator, with a [`Norm`](enum.Norm.html) and scale per column.
+    pub fn new(norm: &[Norm], scale: &[f64]) -> Self {
+        assert_eq!(norm.len(), scale.len());
+        let params = norm
+            .iter()
+            .map(|n| match n {
+                Norm::Unit => (std::f64::MAX, std::f64::MIN),
+                _ => (0.0, 0.0),
+            })
+            .collect();
+        NormAccumulator {
+            norm: norm.to_vec(),
+            scale: scale.to_vec(),
+            counts: vec![0; norm.len()],
+            params,
+        }
+    }
+
+    /// Adds a row to the statistics. NaN values are ignored.
+    pub fn push_row(&mut self, row: &[f64]) {
+        assert_eq!(row.len(), self.norm.len());
         for (i, v) in row.iter().enumerate() {
-            if !v.is_nan() {
-                let norm = &norm[i];
-                match norm {
-                    Norm::Unit => {
-                        if *v < params[i].0 {
-                            params[i].0 = *v
-                        }
-                        if *v > params[i].1 {
-                            params[i].1 = *v
-                        }
-                    }
-                    Norm::Gauss => {
-                        params[i].0 += *v;
-                        params[i].1 += v.powi(2);
-                    }
-                    Norm::None => {}
-                }
-                counts[i] += 1;
-            }
+            self.push_value(i, *v);
         }
     }
-    //println!("Params: {:?}", params);
-    //println!("Counts: {:?}", counts);
-    let denorm: Vec<_> = params
-        .iter()
-        .zip(counts)
-        .zip(norm)
-        .zip(scale)
-        .map(|((((p1, p2), count), norm), scale)| match norm {
+
+    /// Adds the values of a single column to the statistics, e.g. from a [`ColumnMajor`](../../data/struct.ColumnMajor.html) copy.
+    /// NaN values are ignored.
+    pub fn push_column(&mut self, column: usize, values: &[f64]) {
+        for v in values {
+            self.push_value(column, *v);
+        }
+    }
+
+    fn push_value(&mut self, i: usize, v: f64) {
+        if v.is_nan() {
+            return;
+        }
+        self.counts[i] += 1;
+        let params = &mut self.params[i];
+        match self.norm[i] {
             Norm::Unit => {
-                let sc = scale / (p2 - p1);
-                LinearTransform {
-                    //scale: scale * 1.0 / (p2 - p1),
-                    //offset: -*p1,
-                    scale: sc,
-                    offset: -*p1 * sc,
+                if v < params.0 {
+                    params.0 = v
+                }
+                if v > params.1 {
+                    params.1 = v
                 }
             }
             Norm::Gauss => {
-                let sd = ((count as f64 * p2 - p1.powi(2)) / (count * (count - 1)) as f64).sqrt();
-                let mean = p1 / count as f64;
-                let sc = scale / (2.0 * sd);
-                LinearTransform {
-                    //scale: scale * 1.0 / (2.0 * sd),
-                    //offset: -(mean - sd),
-                    scale: sc,
-                    offset: -(mean - sd) * sc,
-                }
+                // Welford: params are (mean, sum of squared deviations)
+                let delta = v - params.0;
+                params.0 += delta / self.counts[i] as f64;
+                params.1 += delta * (v - params.0);
             }
-            Norm::None => LinearTransform {
-                scale: *scale,
-                offset: 0.0,
-            },
-        })
-        .collect();
+            Norm::None => {}
+        }
+    }
+
+    /// Number of non-NaN values ingested per column.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// Normalization transforms, one per column.
+    pub fn transforms(&self) -> Vec<LinearTransform> {
+        self.params
+            .iter()
+            .zip(&self.counts)
+            .zip(&self.norm)
+            .zip(&self.scale)
+            .map(|((((p1, p2), count), norm), scale)| match norm {
+                Norm::Unit => {
+                    let sc = scale / (p2 - p1);
+                    LinearTransform {
+                        scale: sc,
+                        offset: -*p1 * sc,
+                    }
+                }
+                Norm::Gauss => {
+                    let sd = (p2 / (*count as f64 - 1.0)).sqrt();
+                    let sc = scale / (2.0 * sd);
+                    LinearTransform {
+                        scale: sc,
+                        offset: -(p1 - sd) * sc,
+                    }
+                }
+                Norm::None => LinearTransform {
+                    scale: *scale,
+                    offset: 0.0,
+                },
+            })
+            .collect()
+    }
+
+    /// De-normalization transforms, one per column, as returned by [`normalize`](fn.normalize.html).
+    pub fn denorm(&self) -> Vec<LinearTransform> {
+        self.transforms().iter().map(|tr| tr.inverse()).collect()
+    }
+}
+
+/// Normalize a data frame, with a [`Norm`](struct.Norm.html) and scale per column.
+///
+/// Statistics are calculated column by column on a [column-major copy](../../data/struct.DataFrame.html#method.to_column_major),
+/// using a [`NormAccumulator`](struct.NormAccumulator.html),
+/// i.e. with Welford's algorithm for `Norm::Gauss`, which is numerically stable also for large values.
+/// # Returns
+/// A tuple of: (normalized data frame, vector of [`LinearTransform`](struct.LinearTransform.html) for de-normalization, one per column).
+pub fn normalize(
+    data: &DataFrame,
+    norm: &[Norm],
+    scale: &[f64],
+) -> (DataFrame, Vec<LinearTransform>) {
+    let mut acc = NormAccumulator::new(norm, scale);
+    for (col, values) in data.to_column_major().iter_columns().enumerate() {
+        acc.push_column(col, values);
+    }
+    let denorm = acc.transforms();
 
     let cols: Vec<_> = data.columns().iter().map(|x| &**x).collect();
     let mut df = DataFrame::empty(&cols);
@@ -149,6 +211,36 @@ pub fn normalize(
     (df, denorm)
 }
 
+/// Normalize a data frame jointly, with a single [`Norm`](struct.Norm.html) and scale for all columns.
+///
+/// In contrast to [`normalize`](fn.normalize.html), which transforms each column independently,
+/// this uses the global statistics of all values (e.g. the global min/max for `Norm::Unit`).
+/// Thus, all columns are transformed the same way, and relations between columns are preserved.
+/// Useful for data where all columns share a scale, like image pixels.
+/// # Returns
+/// A tuple of: (normalized data frame, vector of [`LinearTransform`](struct.LinearTransform.html) for de-normalization, one per column, all equal).
+pub fn normalize_joint(
+    data: &DataFrame,
+    norm: &Norm,
+    scale: f64,
+) -> (DataFrame, Vec<LinearTransform>) {
+    // joint statistics over all values, as a single pseudo-column
+    let mut acc = NormAccumulator::new(&[norm.clone()], &[scale]);
+    for v in data.data() {
+        acc.push_row(&[*v]);
+    }
+    let trans = acc.transforms().remove(0);
+
+    let cols: Vec<_> = data.columns().iter().map(|x| &**x).collect();
+    let mut df = DataFrame::empty(&cols);
+    for row in data.iter_rows() {
+        df.push_row_iter(row.iter().map(|v| trans.transform(*v)));
+    }
+
+    let denorm = vec![trans.inverse(); data.ncols()];
+    (df, denorm)
+}
+
 /// De-normalize a data frame, with a [`LinearTransform`](struct.LinearTransform.html) per column, as obtained from [`normalize`](fn.normalize.html).
 /// # Returns
 /// A de-normalized data frame
@@ -192,7 +284,9 @@ pub fn denormalize_columns(
 
 #[cfg(test)]
 mod tests {
-    use crate::calc::norm::{denormalize, denormalize_columns, normalize, Norm};
+    use crate::calc::norm::{
+        denormalize, denormalize_columns, normalize, normalize_joint, Norm, NormAccumulator,
+    };
     use crate::data::DataFrame;
     use rand::prelude::*;
     use statistical as stats;
@@ -246,4 +340,97 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn normalization_precision() {
+        let values: Vec<_> = (0..1000).map(|i| 1e9 + (i % 100) as f64 * 0.1).collect();
+        let rows: Vec<_> = values.iter().map(|v| vec![*v]).collect();
+        let data = DataFrame::from_rows(&["A"], &rows);
+
+        let (_df, denorm) = normalize(&data, &[Norm::Gauss], &[1.0]);
+        let sd = 0.5 * denorm[0].scale();
+
+        let sd_expected = stats::standard_deviation(&values, None);
+        assert!(((sd - sd_expected) / sd_expected).abs() < 1e-6);
+
+        let rows: Vec<_> = values.chunks(2).map(|v| v.to_vec()).collect();
+        let data = DataFrame::from_rows(&["A", "B"], &rows);
+
+        let (_df, denorm) = normalize_joint(&data, &Norm::Gauss, 1.0);
+        let sd = 0.5 * denorm[0].scale();
+        assert!(((sd - sd_expected) / sd_expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn accumulator() {
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&["A", "B", "C"]);
+
+        let dist = rand::distributions::Normal::new(1.0, 2.0);
+        for i in 0..50 {
+            data.push_row(&[
+                rng.gen_range(-1.0, 5.0),
+                dist.sample(&mut rng),
+                if i % 7 == 0 {
+                    std::f64::NAN
+                } else {
+                    rng.gen_range(-1.0, 1.0)
+                },
+            ]);
+        }
+
+        let norm = [Norm::Unit, Norm::Gauss, Norm::Gauss];
+        let scale = [1.0, 0.5, 2.0];
+        let (_df, denorm) = normalize(&data, &norm, &scale);
+
+        let mut acc = NormAccumulator::new(&norm, &scale);
+        for row in data.iter_rows() {
+            acc.push_row(row);
+        }
+        assert_eq!(acc.counts(), &[50, 50, 42]);
+
+        for (batch, incr) in denorm.iter().zip(acc.denorm()) {
+            assert!((batch.scale() - incr.scale()).abs() < 1e-6);
+            assert!((batch.offset() - incr.offset()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn normalization_joint() {
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&["A", "B", "C"]);
+        for _i in 0..20 {
+            data.push_row(&[
+                rng.gen_range(-1.0, 5.0),
+                rng.gen_range(0.0, 1.0),
+                rng.gen_range(2.0, 3.0),
+            ]);
+        }
+
+        for norm in &[Norm::Unit, Norm::Gauss] {
+            let (df, denorm) = normalize_joint(&data, norm, 1.0);
+
+            assert_eq!(denorm.len(), 3);
+            for de in &denorm[1..] {
+                assert_eq!(de.scale(), denorm[0].scale());
+                assert_eq!(de.offset(), denorm[0].offset());
+            }
+
+            let df2 = denormalize(&df, &denorm);
+            for (row1, row2) in data.iter_rows().zip(df2.iter_rows()) {
+                for (v1, v2) in row1.iter().zip(row2) {
+                    assert!((v1 - v2).abs() < 0.00001);
+                }
+            }
+        }
+
+        let (df, _denorm) = normalize_joint(&data, &Norm::Unit, 1.0);
+        let ranges = df.ranges();
+        let min = ranges.iter().map(|r| r.0).fold(std::f64::MAX, f64::min);
+        let max = ranges.iter().map(|r| r.1).fold(std::f64::MIN, f64::max);
+        assert!(min.abs() < 0.0001);
+        assert!((max - 1.0).abs() < 0.0001);
+        // columns keep their relative ranges
+        assert!(ranges[1].1 - ranges[1].0 < 0.5);
+    }
 }
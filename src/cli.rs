@@ -2,6 +2,7 @@
 use crate::calc::neighborhood::Neighborhood;
 use crate::map::som::DecayParam;
 use crate::proc::InputLayer;
+use rand::Rng;
 use std::fmt;
 use std::str::FromStr;
 use structopt::StructOpt;
@@ -20,6 +21,9 @@ pub struct Cli {
     /// Number of training epochs.
     #[structopt(short, long)]
     epochs: u32,
+    /// Number of randomly sampled rows to train per epoch. Optional, default: all rows.
+    #[structopt(long = "samples-per-epoch")]
+    samples_per_epoch: Option<usize>,
     /// Layer columns. Put layers in quotes: `"X1 X2 X3" "Y1"`
     #[structopt(short, long)]
     layers: Vec<String>,
@@ -47,6 +51,9 @@ pub struct Cli {
     /// Normalizer per layer list (gauss, unit, none). Optional, default: 'gauss' for non-categorical, 'none' for categorical.
     #[structopt(short, long)]
     norm: Vec<String>,
+    /// Scale per layer list, applied after normalization. Optional, default: '1.0 1.0 ...'
+    #[structopt(long)]
+    scale: Vec<f64>,
     /// Learning rate: start, end, type (lin|exp)
     #[structopt(short, long, number_of_values = 3)]
     alpha: Vec<String>,
@@ -71,6 +78,18 @@ pub struct Cli {
     /// Output base path, with base file name. Optional, default: no file output.
     #[structopt(short, long)]
     output: Option<String>,
+    /// Seed for the random number generator, for reproducible results. Optional, default: random seed.
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Early stopping: number of epochs without improvement of the quantization error. Optional, default: no early stopping.
+    #[structopt(long)]
+    patience: Option<usize>,
+    /// Early stopping: minimum improvement of the quantization error. Optional, default: '0.0'.
+    #[structopt(long = "min-delta")]
+    min_delta: Option<f64>,
+    /// Print quantization error, topographic error and number of dead units after training, as 'key=value' lines.
+    #[structopt(long)]
+    report: bool,
 
     /// Keep the terminal and UI open after processing and wait for user key press.
     #[structopt(long)]
@@ -82,22 +101,40 @@ impl FromStr for Cli {
 
     /// Parses a string into a Cli.
     fn from_str(str: &str) -> Result<Self, Self::Err> {
-        let quote_parts: Vec<_> = str.split('"').collect();
-        let mut args: Vec<String> = vec![];
-        for (i, part) in quote_parts.iter().enumerate() {
-            let part = part.trim();
-            if i % 2 == 0 {
-                args.extend(
-                    part.split(' ')
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty()),
-                );
-            } else {
-                args.push(part.to_string());
-            }
+        Ok(Cli::from_iter(split_args(str).iter()))
+    }
+}
+
+/// Splits a string into arguments at whitespace. Quoted parts are kept together, without the quotes.
+fn split_args(str: &str) -> Vec<String> {
+    let quote_parts: Vec<_> = str.split('"').collect();
+    let mut args: Vec<String> = vec![];
+    for (i, part) in quote_parts.iter().enumerate() {
+        let part = part.trim();
+        if i % 2 == 0 {
+            args.extend(
+                part.split_whitespace()
+                    .map(|s| s.to_string())
+                    .filter(|s| !s.is_empty()),
+            );
+        } else {
+            args.push(part.to_string());
         }
-        Ok(Cli::from_iter(args.iter()))
     }
+    args
+}
+
+/// Removes a `#` comment from a line of an options file. `#` inside quotes is not treated as a comment.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
 }
 
 /// Parsed command line arguments.
@@ -106,6 +143,7 @@ pub struct CliParsed {
     pub file: String,
     pub size: (usize, usize),
     pub epochs: u32,
+    pub samples_per_epoch: Option<usize>,
     pub layers: Vec<InputLayer>,
     pub preserve: Vec<String>,
     pub labels: Option<String>,
@@ -119,16 +157,43 @@ pub struct CliParsed {
     pub no_data: String,
     pub fps: f64,
     pub output: Option<String>,
+    pub seed: u64,
+    pub patience: Option<usize>,
+    pub min_delta: f64,
+    pub report: bool,
     pub wait: bool,
 }
 
 impl CliParsed {
+    /// Parses arguments from an options file (`.koo`), with one or more options per line.
+    ///
+    /// Comments start with `#` and extend to the end of the line. Blank lines are ignored,
+    /// and trailing line continuation characters (`\\` or `^`) are optional.
+    /// Multi-word values, like layer columns, must be quoted.
+    pub fn from_options_file(path: &str) -> Result<Self, ParseCliError> {
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            ParseCliError(format!("Unable to read options file '{}': {}", path, err))
+        })?;
+        let mut args = vec!["kohonen".to_string()];
+        for line in content.lines() {
+            let line = strip_comment(line)
+                .trim()
+                .trim_end_matches(|c| c == '\\' || c == '^');
+            args.extend(split_args(line));
+        }
+        let cli = Cli::from_iter_safe(args.iter()).map_err(|err| {
+            ParseCliError(format!("Unable to parse options file '{}': {}", path, err))
+        })?;
+        Ok(Self::from_cli(cli))
+    }
+
     /// Parse arguments from a [`Cli`](struct.Cli.html).
     pub fn from_cli(mut cli: Cli) -> Self {
         CliParsed {
             file: cli.file.clone(),
             size: (cli.size[0], cli.size[1]),
             epochs: cli.epochs,
+            samples_per_epoch: cli.samples_per_epoch,
             layers: Self::parse_layers(&mut cli),
             preserve: cli.preserve,
             labels: cli.labels,
@@ -145,6 +210,10 @@ impl CliParsed {
             no_data: cli.no_data.unwrap_or_else(|| "NA".to_string()),
             fps: cli.fps.unwrap_or(2.0),
             output: cli.output,
+            seed: cli.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+            patience: cli.patience,
+            min_delta: cli.min_delta.unwrap_or(0.0),
+            report: cli.report,
             wait: cli.wait,
         }
     }
@@ -156,7 +225,7 @@ impl CliParsed {
                 name
             ));
         }
-        DecayParam::new(
+        let param = DecayParam::checked(
             values[0].parse().unwrap_or_else(|err| {
                 panic!("Unable to parse value {} in {}: {}", values[0], name, err)
             }),
@@ -171,6 +240,14 @@ impl CliParsed {
                 _ => panic!("Expected decay funtion 'lin' or 'exp'"),
             },*/
         )
+        .unwrap_or_else(|err| panic!("Invalid values for {}: {}", name, err));
+        if param.is_growing() {
+            println!(
+                "Warning: end value is larger than start value for {}, the parameter grows during training",
+                name
+            );
+        }
+        param
     }
     fn parse_layers(cli: &mut Cli) -> Vec<InputLayer> {
         if cli.layers.is_empty() {
@@ -190,6 +267,9 @@ impl CliParsed {
         if !cli.norm.is_empty() && cli.norm.len() != n_layers {
             panic!("Expected no normalizers, or as many as layers (option --norm)");
         }
+        if !cli.scale.is_empty() && cli.scale.len() != n_layers {
+            panic!("Expected no scales, or as many as layers (option --scale)");
+        }
 
         if cli.weights.is_empty() {
             cli.weights = vec![1.0; n_layers];
@@ -197,6 +277,9 @@ impl CliParsed {
         if cli.categ.is_empty() {
             cli.categ = vec![false; n_layers];
         }
+        if cli.scale.is_empty() {
+            cli.scale = vec![1.0; n_layers];
+        }
         if cli.norm.is_empty() {
             cli.norm = cli
                 .categ
@@ -230,14 +313,15 @@ impl CliParsed {
             .zip(&cli.categ)
             .zip(&cli.metric)
             .zip(&cli.norm)
-            .map(|((((lay, wt), cat), metr), norm)| {
+            .zip(&cli.scale)
+            .map(|(((((lay, wt), cat), metr), norm), scale)| {
                 InputLayer::new(
                     &lay.trim().split(' ').map(|s| &*s).collect::<Vec<_>>(),
                     *wt,
                     *cat,
                     metr.parse().unwrap(),
                     norm.parse().unwrap(),
-                    None,
+                    Some(*scale),
                 )
             })
             .collect::<Vec<_>>()
@@ -273,3 +357,53 @@ mod parse {
     }
 }
 */
+
+#[cfg(test)]
+mod test {
+    use crate::cli::{Cli, CliParsed};
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn parse_scales() {
+        let args = "kohonen --file data.csv --size 4 3 --epochs 10 \
+                    --layers \"A B\" \"C\" --norm gauss unit --scale 2.0 0.5 \
+                    --alpha 0.2 0.01 lin --radius 2 0.5 lin --decay 0.2 0.001 exp";
+        let cli: Cli = args.parse().unwrap();
+        let parsed = CliParsed::from_cli(cli);
+        assert_eq!(parsed.layers.len(), 2);
+        assert_eq!(parsed.layers[0].scale(), 2.0);
+        assert_eq!(parsed.layers[1].scale(), 0.5);
+
+        let args = "kohonen --file data.csv --size 4 3 --epochs 10 --layers \"A B\" \
+                    --alpha 0.2 0.01 lin --radius 2 0.5 lin --decay 0.2 0.001 exp";
+        let parsed = CliParsed::from_cli(args.parse().unwrap());
+        assert_eq!(parsed.layers[0].scale(), 1.0);
+    }
+
+    #[test]
+    fn options_file() {
+        let path = std::env::temp_dir().join("kohonen-options-test.koo");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "# Iris example").unwrap();
+        writeln!(file, "--file data.csv   # training data").unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, "--size 4 3 --epochs 10").unwrap();
+        writeln!(file, "--layers \"A B #1\" \"C\" \\").unwrap();
+        writeln!(file, "  # --categ true true").unwrap();
+        writeln!(file, "--categ false true ^").unwrap();
+        writeln!(file, "--alpha 0.2 0.01 lin").unwrap();
+        writeln!(file, "--radius 2 0.5 lin").unwrap();
+        writeln!(file, "--decay 0.2 0.001 exp").unwrap();
+        drop(file);
+
+        let parsed = CliParsed::from_options_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(parsed.file, "data.csv");
+        assert_eq!(parsed.size, (4, 3));
+        assert_eq!(parsed.layers.len(), 2);
+        assert_eq!(parsed.layers[1].scale(), 1.0);
+        assert!(format!("{:?}", parsed.layers[0]).contains("\"#1\""));
+        assert!(CliParsed::from_options_file("does/not/exist.koo").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
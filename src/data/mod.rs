@@ -1,6 +1,12 @@
 //! Data structures like tables.
 
+use crate::calc::norm::LinearTransform;
+use csv::{ReaderBuilder, WriterBuilder};
+use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashMap;
+use std::error::Error;
 use std::slice::{Chunks, ChunksMut};
 
 /// A data frame with all columns of the same Float type.
@@ -46,6 +52,55 @@ impl DataFrame {
         }
     }
 
+    /// Reads a data frame from a CSV file with a header row.
+    ///
+    /// Empty cells and cells equal to `na` are read as `NaN`.
+    pub fn from_csv(path: &str, delimiter: u8, na: &str) -> Result<Self, Box<dyn Error>> {
+        let mut reader = ReaderBuilder::new().delimiter(delimiter).from_path(path)?;
+        let columns: Vec<String> = reader.headers()?.iter().map(|s| s.to_string()).collect();
+
+        let mut df = DataFrame::empty(&columns.iter().map(|s| &**s).collect::<Vec<_>>());
+        for (rec_idx, record) in reader.records().enumerate() {
+            let rec = record?;
+            let mut row = Vec::with_capacity(columns.len());
+            for (col_idx, value) in rec.iter().enumerate() {
+                let value = value.trim();
+                if value.is_empty() || value == na {
+                    row.push(std::f64::NAN);
+                } else {
+                    row.push(value.parse::<f64>().map_err(|_| {
+                        format!(
+                            "Unable to parse value '{}' in row {}, column '{}'",
+                            value, rec_idx, columns[col_idx]
+                        )
+                    })?);
+                }
+            }
+            df.push_row(&row);
+        }
+        Ok(df)
+    }
+
+    /// Writes the data frame to a CSV file with a header row.
+    ///
+    /// `NaN` values are written as `na`.
+    pub fn to_csv(&self, path: &str, delimiter: u8, na: &str) -> Result<(), Box<dyn Error>> {
+        let mut writer = WriterBuilder::new().delimiter(delimiter).from_path(path)?;
+
+        writer.write_record(&self.columns)?;
+        for row in self.iter_rows() {
+            writer.write_record(row.iter().map(|v| {
+                if v.is_nan() {
+                    na.to_string()
+                } else {
+                    v.to_string()
+                }
+            }))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
     /// Number of columns in the data frame.
     pub fn ncols(&self) -> usize {
         self.ncols
@@ -86,6 +141,8 @@ impl DataFrame {
         self.nrows += 1;
     }
     /// Returns a reference to the value at (row, column).
+    ///
+    /// Bounds are checked in debug builds only. See [`try_get`](#method.try_get) for a checked variant.
     pub fn get(&self, row: usize, col: usize) -> &f64 {
         let idx = self.index(row, col);
         &self.data[idx]
@@ -101,6 +158,24 @@ impl DataFrame {
         self.data[idx] = value
     }
 
+    /// Returns a reference to the value at (row, column), or `None` if out of bounds.
+    pub fn try_get(&self, row: usize, col: usize) -> Option<&f64> {
+        if row < self.nrows && col < self.ncols {
+            Some(&self.data[row * self.ncols + col])
+        } else {
+            None
+        }
+    }
+    /// Returns a row as a slice reference, or `None` if out of bounds.
+    pub fn try_get_row(&self, row: usize) -> Option<&[f64]> {
+        if row < self.nrows {
+            let idx = row * self.ncols;
+            Some(&self.data[idx..idx + self.ncols])
+        } else {
+            None
+        }
+    }
+
     /// Returns a reference to the value at the given index in raw data.
     pub fn get_at(&self, index: usize) -> &f64 {
         &self.data[index]
@@ -126,8 +201,18 @@ impl DataFrame {
     }
 
     /// Returns the raw data index for (row, col).
+    ///
+    /// `row` and `col` must be within the bounds of the data frame, which is checked in debug builds only.
     #[inline]
     pub fn index(&self, row: usize, col: usize) -> usize {
+        debug_assert!(
+            row < self.nrows && col < self.ncols,
+            "Cell ({}, {}) out of bounds for data frame of size ({}, {})",
+            row,
+            col,
+            self.nrows,
+            self.ncols
+        );
         row * self.ncols + col
     }
 
@@ -146,64 +231,370 @@ impl DataFrame {
         self.data.chunks_mut(self.ncols)
     }
 
+    /// Randomly permutes the rows of the data frame in place. Results are reproducible for the same `seed`.
+    pub fn shuffle_rows(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut indices: Vec<_> = (0..self.nrows).collect();
+        rng.shuffle(&mut indices);
+
+        let mut data = Vec::with_capacity(self.data.len());
+        for idx in indices {
+            data.extend_from_slice(self.get_row(idx));
+        }
+        self.data = data;
+    }
+
+    /// Splits the data frame into two, with the first containing the given fraction of rows (rounded),
+    /// and the second containing the remaining rows. Rows are not shuffled, see [`shuffle_rows`](#method.shuffle_rows).
+    pub fn split(&self, frac: f64) -> (DataFrame, DataFrame) {
+        assert!((0.0..=1.0).contains(&frac));
+        let split_row = (frac * self.nrows as f64).round() as usize;
+        let split_idx = split_row * self.ncols;
+        let first = DataFrame {
+            columns: self.columns.clone(),
+            ncols: self.ncols,
+            nrows: split_row,
+            data: self.data[..split_idx].to_vec(),
+        };
+        let second = DataFrame {
+            columns: self.columns.clone(),
+            ncols: self.ncols,
+            nrows: self.nrows - split_row,
+            data: self.data[split_idx..].to_vec(),
+        };
+        (first, second)
+    }
+
+    /// Removes duplicate rows, e.g. for faster training with [`Som::epoch_weighted`](../map/som/struct.Som.html#method.epoch_weighted).
+    ///
+    /// Rows are identical only if all values are exactly equal, bit by bit. `NA` values are equal to each other,
+    /// while e.g. `0.0` and `-0.0` are different.
+    ///
+    /// # Returns
+    /// The unique rows in the order of their first occurrence, and the number of occurrences of each unique row.
+    pub fn deduplicate(&self) -> (DataFrame, Vec<usize>) {
+        let mut unique = DataFrame::empty(&self.columns_ref_vec());
+        let mut counts = Vec::new();
+        let mut index = HashMap::<Vec<u64>, usize>::new();
+        for row in self.iter_rows() {
+            let key: Vec<_> = row.iter().map(|v| v.to_bits()).collect();
+            match index.get(&key) {
+                Some(idx) => counts[*idx] += 1,
+                None => {
+                    index.insert(key, counts.len());
+                    counts.push(1);
+                    unique.push_row(row);
+                }
+            }
+        }
+        (unique, counts)
+    }
+
     /// Copies a column's values into a new vector.
+    ///
+    /// For accessing many columns, [`to_column_major`](#method.to_column_major) is more efficient.
     pub fn copy_column(&self, column: usize) -> Vec<f64> {
         self.iter_rows().map(|row| row[column]).collect()
     }
 
-    /// Returns ranges of columns.
+    /// Creates a column-major copy of the data frame, for efficient per-column operations.
+    ///
+    /// The copy is created block-wise, to keep reads and writes within the cache.
+    pub fn to_column_major(&self) -> ColumnMajor {
+        let mut data = vec![0.0; self.data.len()];
+        for row_start in (0..self.nrows).step_by(TRANSPOSE_BLOCK) {
+            let row_end = cmp::min(row_start + TRANSPOSE_BLOCK, self.nrows);
+            for col_start in (0..self.ncols).step_by(TRANSPOSE_BLOCK) {
+                let col_end = cmp::min(col_start + TRANSPOSE_BLOCK, self.ncols);
+                for row in row_start..row_end {
+                    for col in col_start..col_end {
+                        data[col * self.nrows + row] = self.data[row * self.ncols + col];
+                    }
+                }
+            }
+        }
+        ColumnMajor {
+            ncols: self.ncols,
+            nrows: self.nrows,
+            columns: self.columns.clone(),
+            data,
+        }
+    }
+
+    /// Returns ranges of columns as (min, max). `NA` values are ignored.
+    ///
+    /// Columns without any non-`NA` values have a range of `(NaN, NaN)`, which must be handled by consumers.
+    /// See also [`range_or`](#method.range_or).
+    ///
+    /// Calculated on a [column-major copy](#method.to_column_major).
     pub fn ranges(&self) -> Vec<(f64, f64)> {
+        self.to_column_major().ranges()
+    }
+
+    /// Returns the range of a column as (min, max), or `default` if the column has no non-`NA` values.
+    pub fn range_or(&self, col: usize, default: (f64, f64)) -> (f64, f64) {
+        let mut min = std::f64::MAX;
+        let mut max = std::f64::MIN;
+        let mut any = false;
+        for row in self.iter_rows() {
+            let v = row[col];
+            if !v.is_nan() {
+                if v < min {
+                    min = v;
+                }
+                if v > max {
+                    max = v;
+                }
+                any = true;
+            }
+        }
+        if any {
+            (min, max)
+        } else {
+            default
+        }
+    }
+
+    /// Returns means of columns. `NA` values are ignored.
+    ///
+    /// Calculated on a [column-major copy](#method.to_column_major).
+    pub fn means(&self) -> Vec<f64> {
+        self.to_column_major().means()
+    }
+
+    /// Returns (sample) standard deviations of columns. `NA` values are ignored.
+    ///
+    /// Columns with less than two values have a standard deviation of `NaN`.
+    /// Calculated on a [column-major copy](#method.to_column_major).
+    pub fn std_devs(&self) -> Vec<f64> {
+        self.to_column_major().std_devs()
+    }
+
+    /// Returns the `q`-quantile of each column, with linear interpolation between values. `NA` values are ignored.
+    ///
+    /// Columns without any values have a quantile of `NaN`.
+    pub fn quantiles(&self, q: f64) -> Vec<f64> {
+        self.to_column_major().quantiles(q)
+    }
+
+    /// Returns a summary frame, with one column per column of this frame.
+    ///
+    /// Rows are, in this order: count, mean, std, min, 25%, 50%, 75%, max. `NA` values are ignored.
+    pub fn describe(&self) -> DataFrame {
+        let mut counts = vec![0.0; self.ncols];
+        for row in self.iter_rows() {
+            for (count, v) in counts.iter_mut().zip(row) {
+                if !v.is_nan() {
+                    *count += 1.0;
+                }
+            }
+        }
+        let columns = self.to_column_major();
+        let ranges = columns.ranges();
+        let mins: Vec<_> = ranges.iter().map(|r| r.0).collect();
+        let maxs: Vec<_> = ranges.iter().map(|r| r.1).collect();
+
+        let mut df = DataFrame::empty(&self.columns_ref_vec());
+        df.push_row(&counts);
+        df.push_row(&columns.means());
+        df.push_row(&columns.std_devs());
+        df.push_row(&mins);
+        df.push_row(&columns.quantiles(0.25));
+        df.push_row(&columns.quantiles(0.5));
+        df.push_row(&columns.quantiles(0.75));
+        df.push_row(&maxs);
+        df
+    }
+
+    /// Standardizes all columns to a mean of 0 and a standard deviation of 1. `NA` values are ignored.
+    ///
+    /// Constant columns are only centered, columns without any values are left unchanged.
+    /// # Returns
+    /// A tuple of: (standardized data frame, vector of [`LinearTransform`](../calc/norm/struct.LinearTransform.html) for de-standardization, one per column).
+    pub fn zscore(&self) -> (DataFrame, Vec<LinearTransform>) {
         let ncol = self.ncols;
-        let mut min = vec![std::f64::MAX; ncol];
-        let mut max = vec![std::f64::MIN; ncol];
-        let mut any = vec![false; ncol];
+        // Welford's algorithm, numerically stable also for large values
+        let mut means = vec![0.0; ncol];
+        let mut sq_devs = vec![0.0; ncol];
+        let mut counts = vec![0; ncol];
         for row in self.iter_rows() {
             for col in 0..ncol {
                 let v = row[col];
                 if !v.is_nan() {
-                    if v < min[col] {
-                        min[col] = v;
-                    }
-                    if v > max[col] {
-                        max[col] = v;
-                    }
-                    any[col] = true;
+                    counts[col] += 1;
+                    let delta = v - means[col];
+                    means[col] += delta / counts[col] as f64;
+                    sq_devs[col] += delta * (v - means[col]);
                 }
             }
         }
-        min.into_iter()
-            .zip(max)
-            .enumerate()
-            .map(|(i, (mn, mx))| {
-                if any[i] {
-                    (mn, mx)
+
+        let transforms: Vec<_> = (0..ncol)
+            .map(|col| {
+                let count = counts[col];
+                if count == 0 {
+                    return LinearTransform::new(1.0, 0.0);
+                }
+                let mean = means[col];
+                let var = if count > 1 {
+                    sq_devs[col] / (count - 1) as f64
                 } else {
-                    (std::f64::NAN, std::f64::NAN)
+                    0.0
+                };
+                if var > 0.0 {
+                    let sd = var.sqrt();
+                    LinearTransform::new(1.0 / sd, -mean / sd)
+                } else {
+                    LinearTransform::new(1.0, -mean)
                 }
             })
+            .collect();
+
+        let mut df = DataFrame::empty(&self.columns_ref_vec());
+        for row in self.iter_rows() {
+            df.push_row_iter(transforms.iter().zip(row).map(|(tr, v)| tr.transform(*v)));
+        }
+
+        let inverse = transforms.iter().map(|tr| tr.inverse()).collect();
+        (df, inverse)
+    }
+}
+
+/// Block size for cache-friendly transposition between row-major and column-major layout.
+const TRANSPOSE_BLOCK: usize = 32;
+
+/// A column-major copy of a [`DataFrame`](struct.DataFrame.html), for efficient per-column operations.
+///
+/// Created by [`DataFrame::to_column_major`](struct.DataFrame.html#method.to_column_major).
+pub struct ColumnMajor {
+    ncols: usize,
+    nrows: usize,
+    columns: Vec<String>,
+    data: Vec<f64>,
+}
+
+impl ColumnMajor {
+    /// Number of columns.
+    pub fn ncols(&self) -> usize {
+        self.ncols
+    }
+    /// Number of rows.
+    pub fn nrows(&self) -> usize {
+        self.nrows
+    }
+    /// The column names.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+    /// The values of a column.
+    pub fn column(&self, column: usize) -> &[f64] {
+        &self.data[(column * self.nrows)..((column + 1) * self.nrows)]
+    }
+    /// Iterator over columns.
+    pub fn iter_columns(&self) -> Chunks<f64> {
+        // max(1) as chunks() panics for a size of 0
+        self.data.chunks(cmp::max(self.nrows, 1))
+    }
+
+    /// Returns ranges of columns as (min, max). `NA` values are ignored.
+    ///
+    /// Columns without any non-`NA` values have a range of `(NaN, NaN)`.
+    /// See [`DataFrame::ranges`](struct.DataFrame.html#method.ranges).
+    pub fn ranges(&self) -> Vec<(f64, f64)> {
+        (0..self.ncols)
+            .map(|col| {
+                let values = self.column(col).iter().filter(|v| !v.is_nan());
+                values.fold((std::f64::NAN, std::f64::NAN), |(min, max), v| {
+                    (min.min(*v), max.max(*v))
+                })
+            })
             .collect()
     }
 
-    /// Returns means of columns.
+    /// Returns means of columns. `NA` values are ignored.
     pub fn means(&self) -> Vec<f64> {
-        let ncol = self.ncols;
-        //let nrows = self.nrows;
+        (0..self.ncols)
+            .map(|col| {
+                let (sum, count) = self
+                    .column(col)
+                    .iter()
+                    .filter(|v| !v.is_nan())
+                    .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                sum / count as f64
+            })
+            .collect()
+    }
 
-        let mut means = vec![0.0; ncol];
-        let mut counts = vec![0; ncol];
-        for row in self.iter_rows() {
-            for col in 0..ncol {
-                let v = row[col];
-                if !v.is_nan() {
-                    means[col] += v;
-                    counts[col] += 1;
+    /// Returns (sample) standard deviations of columns. `NA` values are ignored.
+    ///
+    /// Columns with less than two values have a standard deviation of `NaN`.
+    pub fn std_devs(&self) -> Vec<f64> {
+        self.means()
+            .iter()
+            .enumerate()
+            .map(|(col, mean)| {
+                let (sum_sq, count) = self
+                    .column(col)
+                    .iter()
+                    .filter(|v| !v.is_nan())
+                    .fold((0.0, 0), |(sum_sq, count), v| {
+                        (sum_sq + (v - mean).powi(2), count + 1)
+                    });
+                if count > 1 {
+                    (sum_sq / (count - 1) as f64).sqrt()
+                } else {
+                    std::f64::NAN
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the `q`-quantile of each column, with linear interpolation between values. `NA` values are ignored.
+    ///
+    /// Columns without any values have a quantile of `NaN`.
+    pub fn quantiles(&self, q: f64) -> Vec<f64> {
+        assert!((0.0..=1.0).contains(&q));
+        (0..self.ncols)
+            .map(|col| {
+                let mut values: Vec<_> = self
+                    .column(col)
+                    .iter()
+                    .copied()
+                    .filter(|v| !v.is_nan())
+                    .collect();
+                if values.is_empty() {
+                    return std::f64::NAN;
+                }
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let pos = q * (values.len() - 1) as f64;
+                let lower = pos.floor() as usize;
+                let upper = pos.ceil() as usize;
+                let frac = pos - lower as f64;
+                values[lower] + (values[upper] - values[lower]) * frac
+            })
+            .collect()
+    }
+
+    /// Converts back to a row-major [`DataFrame`](struct.DataFrame.html).
+    pub fn to_row_major(&self) -> DataFrame {
+        let mut data = vec![0.0; self.data.len()];
+        for col_start in (0..self.ncols).step_by(TRANSPOSE_BLOCK) {
+            let col_end = cmp::min(col_start + TRANSPOSE_BLOCK, self.ncols);
+            for row_start in (0..self.nrows).step_by(TRANSPOSE_BLOCK) {
+                let row_end = cmp::min(row_start + TRANSPOSE_BLOCK, self.nrows);
+                for col in col_start..col_end {
+                    for row in row_start..row_end {
+                        data[row * self.ncols + col] = self.data[col * self.nrows + row];
+                    }
                 }
             }
         }
-        for i in 0..ncol {
-            means[i] /= counts[i] as f64;
+        DataFrame {
+            ncols: self.ncols,
+            nrows: self.nrows,
+            columns: self.columns.clone(),
+            data,
         }
-        means
     }
 }
 
@@ -211,6 +602,28 @@ impl DataFrame {
 mod test {
     use crate::data::DataFrame;
 
+    #[test]
+    fn deduplicate() {
+        let nan = std::f64::NAN;
+        let df = DataFrame::from_rows(
+            &["A", "B"],
+            &[
+                vec![1.0, 2.0],
+                vec![3.0, nan],
+                vec![1.0, 2.0],
+                vec![1.0, 2.5],
+                vec![3.0, nan],
+                vec![1.0, 2.0],
+            ],
+        );
+        let (unique, counts) = df.deduplicate();
+        assert_eq!(unique.nrows(), 3);
+        assert_eq!(counts, vec![3, 2, 1]);
+        assert_eq!(unique.get_row(0), &[1.0, 2.0]);
+        assert!(unique.get_row(1)[1].is_nan());
+        assert_eq!(unique.get_row(2), &[1.0, 2.5]);
+    }
+
     #[test]
     fn create_df() {
         let cols = ["A", "B", "C", "D"];
@@ -286,4 +699,223 @@ mod test {
 
         assert_eq!(ranges, vec![(1.0, 3.0), (2.0, 4.0), (3.0, 5.0), (4.0, 6.0)]);
     }
+
+    #[test]
+    fn shuffle_split() {
+        let cols = ["A", "B"];
+        let mut df1 = DataFrame::empty(&cols);
+        for i in 0..20 {
+            df1.push_row(&[i as f64, -(i as f64)]);
+        }
+        let mut df2 = DataFrame::from_rows(
+            &cols,
+            &df1.iter_rows().map(|r| r.to_vec()).collect::<Vec<_>>(),
+        );
+
+        df1.shuffle_rows(123);
+        df2.shuffle_rows(123);
+        assert_eq!(df1.data(), df2.data());
+        assert_eq!(df1.nrows(), 20);
+        for row in df1.iter_rows() {
+            assert_eq!(row[0], -row[1]);
+        }
+
+        let (train, test) = df1.split(0.75);
+        assert_eq!(train.nrows(), 15);
+        assert_eq!(test.nrows(), 5);
+        assert_eq!(train.get_row(0), df1.get_row(0));
+        assert_eq!(test.get_row(0), df1.get_row(15));
+    }
+
+    #[test]
+    fn ranges_nan() {
+        let cols = ["A", "B"];
+        let mut df = DataFrame::empty(&cols);
+
+        df.push_row(&[1.0, std::f64::NAN]);
+        df.push_row(&[3.0, std::f64::NAN]);
+
+        let ranges = df.ranges();
+        assert_eq!(ranges[0], (1.0, 3.0));
+        assert!(ranges[1].0.is_nan() && ranges[1].1.is_nan());
+
+        assert_eq!(df.range_or(0, (0.0, 1.0)), (1.0, 3.0));
+        assert_eq!(df.range_or(1, (0.0, 1.0)), (0.0, 1.0));
+    }
+
+    #[test]
+    fn zscore() {
+        let cols = ["A", "B", "C"];
+        let mut df = DataFrame::empty(&cols);
+
+        df.push_row(&[1.0, 5.0, 2.0]);
+        df.push_row(&[2.0, 5.0, std::f64::NAN]);
+        df.push_row(&[4.0, 5.0, 6.0]);
+        df.push_row(&[5.0, 5.0, 7.0]);
+
+        let (z, inverse) = df.zscore();
+
+        let means = z.means();
+        assert!(means[0].abs() < 0.00001);
+        assert!(means[1].abs() < 0.00001);
+        assert!(means[2].abs() < 0.00001);
+        assert!(z.get(1, 2).is_nan());
+
+        for (row1, row2) in df.iter_rows().zip(z.iter_rows()) {
+            for ((v1, v2), tr) in row1.iter().zip(row2).zip(&inverse) {
+                if v1.is_nan() {
+                    assert!(v2.is_nan());
+                } else {
+                    assert!((v1 - tr.transform(*v2)).abs() < 0.00001);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zscore_precision() {
+        let rows: Vec<_> = (0..1000)
+            .map(|i| vec![1e9 + (i % 100) as f64 * 0.1])
+            .collect();
+        let df = DataFrame::from_rows(&["A"], &rows);
+
+        let (z, inverse) = df.zscore();
+        let sd_expected = df.std_devs()[0];
+
+        assert!(z.means()[0].abs() < 1e-5);
+        assert!((z.std_devs()[0] - 1.0).abs() < 1e-5);
+        assert!(((inverse[0].scale() - sd_expected) / sd_expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn describe() {
+        let df = DataFrame::from_rows(
+            &["A", "B"],
+            &[
+                vec![1.0, std::f64::NAN],
+                vec![2.0, 5.0],
+                vec![3.0, 5.0],
+                vec![4.0, std::f64::NAN],
+                vec![5.0, 5.0],
+            ],
+        );
+        let desc = df.describe();
+
+        assert_eq!(desc.columns(), df.columns());
+        assert_eq!(desc.nrows(), 8);
+        assert_eq!(
+            desc.copy_column(0),
+            vec![5.0, 3.0, 2.5f64.sqrt(), 1.0, 2.0, 3.0, 4.0, 5.0]
+        );
+        assert_eq!(
+            desc.copy_column(1),
+            vec![3.0, 5.0, 0.0, 5.0, 5.0, 5.0, 5.0, 5.0]
+        );
+
+        let empty = DataFrame::from_rows(&["A"], &[vec![std::f64::NAN]]);
+        let desc = empty.describe();
+        assert_eq!(*desc.get(0, 0), 0.0);
+        assert!(desc.copy_column(0)[1..].iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let df = DataFrame::from_rows(
+            &["A", "B", "C"],
+            &[
+                vec![1.0, 2.5, std::f64::NAN],
+                vec![-3.0, 0.125, 1e-3],
+                vec![std::f64::NAN, 4.0, 5.0],
+            ],
+        );
+
+        let mut path = std::env::temp_dir();
+        path.push("kohonen_test_df_round_trip.csv");
+        let path = path.to_str().unwrap();
+
+        df.to_csv(path, b';', "NA").unwrap();
+        let df2 = DataFrame::from_csv(path, b';', "NA").unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(df2.columns(), df.columns());
+        assert_eq!(df2.nrows(), df.nrows());
+        for (v1, v2) in df.data().iter().zip(df2.data()) {
+            assert!(v1 == v2 || (v1.is_nan() && v2.is_nan()));
+        }
+    }
+
+    #[test]
+    fn checked_access() {
+        let df = DataFrame::from_rows(&["A", "B"], &[vec![1.0, 2.0], vec![3.0, 4.0]]);
+
+        assert_eq!(df.try_get(1, 0), Some(&3.0));
+        assert_eq!(df.try_get(1, 1), Some(df.get(1, 1)));
+        assert_eq!(df.try_get(2, 0), None);
+        assert_eq!(df.try_get(0, 2), None);
+
+        assert_eq!(df.try_get_row(1), Some(&[3.0, 4.0][..]));
+        assert_eq!(df.try_get_row(2), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of bounds")]
+    fn unchecked_access_out_of_bounds() {
+        let df = DataFrame::from_rows(&["A", "B"], &[vec![1.0, 2.0], vec![3.0, 4.0]]);
+        // Would silently return the value at (1, 0) without bounds check
+        df.get(0, 2);
+    }
+
+    #[test]
+    fn column_major() {
+        let nan = std::f64::NAN;
+        let rows: Vec<_> = (0..50)
+            .map(|r| {
+                (0..40)
+                    .map(|c| {
+                        if (r + c) % 7 == 0 {
+                            nan
+                        } else {
+                            ((r * 40 + c) % 13) as f64
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let names: Vec<_> = (0..40).map(|c| format!("C{}", c)).collect();
+        let df = DataFrame::from_rows(&names.iter().map(|n| &**n).collect::<Vec<_>>(), &rows);
+
+        let cm = df.to_column_major();
+        assert_eq!(cm.nrows(), 50);
+        assert_eq!(cm.ncols(), 40);
+        assert_eq!(cm.columns(), df.columns());
+        for (col, values) in cm.iter_columns().enumerate() {
+            let expected = df.copy_column(col);
+            assert_eq!(values.len(), expected.len());
+            for (a, b) in values.iter().zip(&expected) {
+                assert!(a == b || (a.is_nan() && b.is_nan()));
+            }
+        }
+        for (col, (min, max)) in cm.ranges().iter().enumerate() {
+            let values: Vec<_> = df
+                .copy_column(col)
+                .into_iter()
+                .filter(|v| !v.is_nan())
+                .collect();
+            assert_eq!(*min, values.iter().cloned().fold(std::f64::MAX, f64::min));
+            assert_eq!(*max, values.iter().cloned().fold(std::f64::MIN, f64::max));
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            assert!((cm.means()[col] - mean).abs() < 1e-12);
+        }
+
+        let back = cm.to_row_major();
+        assert_eq!(back.columns(), df.columns());
+        for (a, b) in back.data().iter().zip(df.data()) {
+            assert!(a == b || (a.is_nan() && b.is_nan()));
+        }
+
+        let empty = DataFrame::empty(&["A", "B"]).to_column_major();
+        assert_eq!(empty.column(1).len(), 0);
+        assert!(empty.ranges()[0].0.is_nan());
+    }
 }
@@ -1,12 +1,12 @@
 use easy_graph::ui::window::WindowBuilder;
 use kohonen::cli::{Cli, CliParsed};
-use kohonen::map::som::Som;
+use kohonen::map::som::{EarlyStopping, Som, TrainingMetadata};
 use kohonen::proc::{Processor, ProcessorBuilder};
 use kohonen::ui::LayerView;
+use std::env;
 use std::fs::File;
 use std::io::Write;
 use std::time::{Duration, Instant};
-use std::{env, fs};
 use structopt::StructOpt;
 
 fn main() {
@@ -21,21 +21,14 @@ fn main() {
         env::args().collect()
     };
     let mut parsed: CliParsed = if args.len() == 2 && !args[1].starts_with('-') {
-        let mut content = fs::read_to_string(&args[1]).unwrap_or_else(|err| {
-            panic!(
-                "Something went wrong reading the options file {:?}: {}",
-                &args[1], err,
-            )
-        });
-        content = "kohonen ".to_string() + &content.replace("\r\n", " ").replace("\n", " ");
-        let cli: Cli = content.parse().unwrap();
-        CliParsed::from_cli(cli)
+        CliParsed::from_options_file(&args[1]).unwrap_or_else(|err| panic!("{}", err))
     } else {
         let cli = Cli::from_args();
         CliParsed::from_cli(cli)
     };
 
     println!("{:#?}", parsed);
+    println!("Seed: {}", parsed.seed);
 
     let proc = ProcessorBuilder::new(
         &parsed.layers,
@@ -46,9 +39,14 @@ fn main() {
     )
     .with_delimiter(b';')
     .with_no_data(&parsed.no_data)
+    .with_label_seed(Some(parsed.seed))
     .build_from_file(&parsed.file)
     .unwrap();
 
+    for warning in proc.check_scaling() {
+        println!("Warning: {}", warning);
+    }
+
     let mut som = proc.create_som(
         parsed.size.1,
         parsed.size.0,
@@ -57,6 +55,7 @@ fn main() {
         parsed.alpha.clone(),
         parsed.radius.clone(),
         parsed.decay.clone(),
+        Some(parsed.seed),
     );
 
     let mut viewers: Option<Vec<LayerView>> = if parsed.gui {
@@ -78,13 +77,26 @@ fn main() {
         None
     };
 
+    let mut stopping = parsed
+        .patience
+        .map(|patience| EarlyStopping::new(patience, parsed.min_delta));
+    let mut stopped = false;
+
     let mut done = false;
 
     let start = Instant::now();
 
     if let Some(views) = &mut viewers {
         while views.iter().any(|v| v.is_open()) {
-            let res = som.epoch(&proc.data(), None);
+            let mut res = if stopped {
+                None
+            } else {
+                som.epoch(&proc.data(), parsed.samples_per_epoch)
+            };
+            if res.is_some() && stop_early(&mut stopping, &proc, &som) {
+                stopped = true;
+                res = None;
+            }
             let label_data = match proc.labels() {
                 Some(lab) => Some((proc.data(), lab)),
                 None => None,
@@ -95,6 +107,9 @@ fn main() {
             if res.is_none() {
                 if !done {
                     println!("Elapsed: {:?}", start.elapsed());
+                    if parsed.report {
+                        report(&proc, &som);
+                    }
                     write_output(&parsed, &proc, &som);
                     done = true;
                 }
@@ -107,8 +122,15 @@ fn main() {
         }
         parsed.wait = false;
     } else {
-        while let Some(()) = som.epoch(&proc.data(), None) {}
+        while let Some(()) = som.epoch(&proc.data(), parsed.samples_per_epoch) {
+            if stop_early(&mut stopping, &proc, &som) {
+                break;
+            }
+        }
         println!("Elapsed: {:?}", start.elapsed());
+        if parsed.report {
+            report(&proc, &som);
+        }
         write_output(&parsed, &proc, &som);
     }
 
@@ -117,6 +139,32 @@ fn main() {
     }
 }
 
+/// Checks for early stopping after an epoch. Returns `true` if training should stop.
+fn stop_early(stopping: &mut Option<EarlyStopping>, proc: &Processor, som: &Som) -> bool {
+    if let Some(stop) = stopping {
+        let qe = som.quantization_error(proc.data());
+        if stop.update(qe) {
+            println!(
+                "Stopped early at epoch {}. Quantization error: {} (best {})",
+                som.current_epoch(),
+                qe,
+                stop.best()
+            );
+            return true;
+        }
+    }
+    false
+}
+
+/// Prints quality measures of the trained SOM, as `key=value` lines.
+fn report(proc: &Processor, som: &Som) {
+    let data = proc.data();
+    let dead_units = som.hit_counts(data).iter().filter(|c| **c == 0).count();
+    println!("quantization_error={}", som.quantization_error(data));
+    println!("topographic_error={}", som.topographic_error(data));
+    println!("dead_units={}", dead_units);
+}
+
 fn write_output(parsed: &CliParsed, proc: &Processor, som: &Som) {
     if let Some(out) = &parsed.output {
         let units_file = format!("{}-units.csv", &out);
@@ -128,7 +176,8 @@ fn write_output(parsed: &CliParsed, proc: &Processor, som: &Som) {
         proc.write_normalization(&som, &norm_file).unwrap();
 
         let som_file = format!("{}-som.json", &out);
-        let serialized = serde_json::to_string_pretty(&(som, proc.denorm())).unwrap();
+        let metadata = TrainingMetadata::new(&parsed.file, som, proc.data());
+        let serialized = som.save_to_json(proc.denorm(), Some(&metadata)).unwrap();
         let mut file = File::create(som_file).unwrap();
         file.write_all(serialized.as_bytes()).unwrap();
     }
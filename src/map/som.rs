@@ -1,14 +1,19 @@
 //! Super-SOM for flexible use as unsupervised or supervised SOM. Core types.
 
-use crate::calc::metric::Metric;
-use crate::calc::neighborhood::Neighborhood;
+use crate::calc::metric::{DistanceMetric, GridMetric, Metric, WeightedEuclidean};
+use crate::calc::neighborhood::{Neighborhood, NeighborhoodFn};
 use crate::calc::nn;
+use crate::calc::norm::LinearTransform;
 use crate::data::DataFrame;
-use crate::ParseEnumError;
+use crate::{DataTypeError, ParseEnumError};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::cmp;
+use std::error::Error;
+use std::ops::Range;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// SOM training parameters
 #[derive(Serialize, Deserialize)]
@@ -21,6 +26,19 @@ pub struct SomParams {
     decay: DecayParam,
     layers: Vec<Layer>,
     start_columns: Vec<usize>,
+    seed: Option<u64>,
+    grid_metric: GridMetric,
+    neighborhood_late: Option<(f64, Neighborhood)>,
+    decay_enabled: bool,
+    conscience: Option<(f64, f64)>,
+    #[serde(default)]
+    radius_mode: RadiusMode,
+    #[serde(default)]
+    min_weight: f64,
+    #[serde(default)]
+    on_the_fly_distances: bool,
+    #[serde(skip)]
+    custom_neighborhood: Option<Arc<dyn NeighborhoodFn>>,
 }
 
 impl SomParams {
@@ -39,7 +57,16 @@ impl SomParams {
             radius,
             decay,
             layers: vec![],
-            start_columns: vec![0],
+            start_columns: Self::calc_start_columns(&[]),
+            seed: None,
+            grid_metric: GridMetric::Euclidean,
+            neighborhood_late: None,
+            decay_enabled: true,
+            conscience: None,
+            radius_mode: RadiusMode::Absolute,
+            min_weight: 0.0,
+            on_the_fly_distances: false,
+            custom_neighborhood: None,
         }
     }
 
@@ -61,6 +88,129 @@ impl SomParams {
             decay,
             layers,
             start_columns: start_cols,
+            seed: None,
+            grid_metric: GridMetric::Euclidean,
+            neighborhood_late: None,
+            decay_enabled: true,
+            conscience: None,
+            radius_mode: RadiusMode::Absolute,
+            min_weight: 0.0,
+            on_the_fly_distances: false,
+            custom_neighborhood: None,
+        }
+    }
+
+    /// Sets the seed for the SOM's random number generator, for reproducible training.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the distance metric between units on the SOM grid. Default `GridMetric::Euclidean`.
+    pub fn with_grid_metric(mut self, grid_metric: GridMetric) -> Self {
+        self.grid_metric = grid_metric;
+        self
+    }
+
+    /// Sets a neighborhood function to be used instead of the initial one after the given fraction of epochs.
+    ///
+    /// E.g., a Gaussian neighborhood for broad early adaptation and a sharp neighborhood for fine tuning.
+    pub fn with_neighborhood_schedule(mut self, fraction: f64, late: Neighborhood) -> Self {
+        self.neighborhood_late = Some((fraction, late));
+        self
+    }
+
+    /// Enables or disables weight decay (i.e. mean reversion) of units after each epoch. Default `true`.
+    pub fn with_decay_enabled(mut self, enabled: bool) -> Self {
+        self.decay_enabled = enabled;
+        self
+    }
+
+    /// Enables the conscience mechanism (DeSieno), which biases the selection of the best-matching unit
+    /// away from units that win too often. Default disabled.
+    ///
+    /// `beta` is the rate for updating the units' win frequencies, `gamma` scales the bias,
+    /// which is subtracted from the distance to each unit: `gamma * (1 / units - frequency)`.
+    pub fn with_conscience(mut self, beta: f64, gamma: f64) -> Self {
+        self.conscience = Some((beta, gamma));
+        self
+    }
+
+    /// Sets how the neighborhood radius is interpreted. Default `RadiusMode::Absolute`.
+    ///
+    /// See [`RadiusMode`](enum.RadiusMode.html).
+    pub fn with_radius_mode(mut self, mode: RadiusMode) -> Self {
+        self.radius_mode = mode;
+        self
+    }
+
+    /// Returns how the neighborhood radius is interpreted.
+    pub fn radius_mode(&self) -> &RadiusMode {
+        &self.radius_mode
+    }
+
+    /// Sets the minimum neighborhood weight for updating a unit during training. Default 0.0.
+    ///
+    /// Units with a smaller neighborhood weight are not updated. Speeds up training of large maps
+    /// with Gaussian neighborhoods, where tiny weights cause updates in a wide window, at the cost of a little accuracy.
+    pub fn with_min_weight(mut self, min_weight: f64) -> Self {
+        self.min_weight = min_weight;
+        self
+    }
+
+    /// Returns the minimum neighborhood weight for updating a unit during training.
+    pub fn min_weight(&self) -> f64 {
+        self.min_weight
+    }
+
+    /// Sets whether grid distances between units are calculated on the fly during training. Default `false`.
+    ///
+    /// By default, a matrix of distances between all pairs of units is pre-calculated, which requires
+    /// `(rows * cols)^2` values, e.g. 800 MB for a 100 x 100 SOM. Calculating distances on the fly
+    /// avoids the matrix, at the cost of some extra computation. Training results are the same.
+    pub fn with_on_the_fly_distances(mut self, on_the_fly: bool) -> Self {
+        self.on_the_fly_distances = on_the_fly;
+        self
+    }
+
+    /// Returns whether grid distances between units are calculated on the fly during training.
+    pub fn on_the_fly_distances(&self) -> bool {
+        self.on_the_fly_distances
+    }
+
+    /// Returns the conscience parameters (beta, gamma), if enabled.
+    pub fn conscience(&self) -> Option<(f64, f64)> {
+        self.conscience
+    }
+
+    /// Returns whether weight decay is enabled.
+    pub fn decay_enabled(&self) -> bool {
+        self.decay_enabled
+    }
+
+    /// Sets a user-defined neighborhood function, used instead of the built-in [`Neighborhood`](../../calc/neighborhood/enum.Neighborhood.html)
+    /// and any neighborhood schedule.
+    ///
+    /// Custom neighborhoods are not serialized. After deserialization, the built-in neighborhood is used.
+    pub fn with_custom_neighborhood(mut self, neighborhood: impl NeighborhoodFn + 'static) -> Self {
+        self.custom_neighborhood = Some(Arc::new(neighborhood));
+        self
+    }
+
+    /// Returns the built-in neighborhood function for the given epoch.
+    pub fn neighborhood(&self, epoch: u32) -> &Neighborhood {
+        match &self.neighborhood_late {
+            Some((fraction, late)) if epoch as f64 >= fraction * self.epochs as f64 => late,
+            _ => &self.neighborhood,
+        }
+    }
+
+    /// Returns the neighborhood function used for the given epoch.
+    /// The user-defined neighborhood if present, the built-in one otherwise.
+    pub fn neighborhood_fn(&self, epoch: u32) -> &dyn NeighborhoodFn {
+        match &self.custom_neighborhood {
+            Some(neigh) => neigh.as_ref(),
+            None => self.neighborhood(epoch),
         }
     }
 
@@ -69,12 +219,63 @@ impl SomParams {
         &self.layers
     }
 
+    /// Returns a mutable reference to the layers, for manual edits.
+    ///
+    /// After adding or removing layers, or changing their number of columns,
+    /// [`recompute_start_columns`](#method.recompute_start_columns) must be called.
+    pub fn layers_mut(&mut self) -> &mut Vec<Layer> {
+        &mut self.layers
+    }
+
     /// Returns a list of the first column index for each layer.
     pub fn start_columns(&self) -> &[usize] {
         &self.start_columns
     }
 
+    /// Recomputes the first column index of each layer from the layers' numbers of columns,
+    /// e.g. after manual layer edits through [`layers_mut`](#method.layers_mut).
+    pub fn recompute_start_columns(&mut self) {
+        self.start_columns = Self::calc_start_columns(&self.layers);
+    }
+
+    /// Returns the range of columns of the given layer.
+    pub fn column_range(&self, layer: usize) -> Range<usize> {
+        let start = self.start_columns[layer];
+        start..(start + self.layers[layer].ncols())
+    }
+
+    /// Returns the index of the layer the given column belongs to.
+    ///
+    /// For simple SOMs without layer definitions, always returns 0.
+    pub fn layer_of_column(&self, col: usize) -> usize {
+        if let Some(last) = self.layers.last() {
+            assert!(
+                col < self.start_columns[self.layers.len() - 1] + last.ncols(),
+                "Column {} out of range",
+                col
+            );
+        }
+        self.start_columns
+            .iter()
+            .rposition(|start| *start <= col)
+            .unwrap()
+    }
+
+    /// Returns the seed for the random number generator, if any.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Returns the distance metric between units on the SOM grid.
+    pub fn grid_metric(&self) -> &GridMetric {
+        &self.grid_metric
+    }
+
+    /// Calculates the first column index of each layer. Simple SOMs without layers have a single start column of 0.
     fn calc_start_columns(layers: &[Layer]) -> Vec<usize> {
+        if layers.is_empty() {
+            return vec![0];
+        }
         let mut result = vec![0; layers.len()];
         let mut start_col = 0;
         for (i, lay) in layers.iter().enumerate() {
@@ -92,6 +293,12 @@ pub struct Layer {
     weight: f64,
     categorical: bool,
     metric: Metric,
+    #[serde(skip)]
+    custom_metric: Option<Arc<dyn DistanceMetric>>,
+    #[serde(default)]
+    whitening: Option<WeightedEuclidean>,
+    #[serde(default = "Layer::default_decay_factor")]
+    decay_factor: f64,
 }
 impl Layer {
     /// Creates a new layer.
@@ -101,8 +308,61 @@ impl Layer {
             weight,
             categorical,
             metric,
+            custom_metric: None,
+            whitening: None,
+            decay_factor: 1.0,
         }
     }
+    fn default_decay_factor() -> f64 {
+        1.0
+    }
+    /// Sets a user-defined distance metric, used instead of the built-in [`Metric`](../../calc/metric/enum.Metric.html).
+    ///
+    /// Custom metrics are not serialized. After deserialization, the layer falls back to the built-in metric.
+    pub fn with_custom_metric(mut self, metric: impl DistanceMetric + 'static) -> Self {
+        self.custom_metric = Some(Arc::new(metric));
+        self
+    }
+    /// Sets whitening for a continuous layer: each column's contribution to the distance is divided by the
+    /// given standard deviation, so that columns with a large spread don't dominate the layer's distance.
+    /// Columns with a standard deviation of zero or `NaN` are left unweighted.
+    ///
+    /// Replaces the built-in Euclidean metric by a [`WeightedEuclidean`](../../calc/metric/struct.WeightedEuclidean.html).
+    /// A user-defined metric takes precedence.
+    pub fn with_whitening(mut self, std_devs: &[f64]) -> Self {
+        assert!(
+            !self.categorical,
+            "Whitening is only supported for continuous layers"
+        );
+        assert_eq!(std_devs.len(), self.ncols);
+        let weights: Vec<_> = std_devs
+            .iter()
+            .map(|sd| if *sd > 0.0 { 1.0 / sd } else { 1.0 })
+            .collect();
+        self.whitening = Some(WeightedEuclidean::new(&weights));
+        self
+    }
+    /// Sets a multiplier for the weight decay (mean reversion) of the layer's columns. Default 1.0.
+    ///
+    /// A factor of 0.0 exempts the layer from decay, e.g. for categorical layers,
+    /// where mean reversion degrades the one-hot structure of unit weights.
+    pub fn with_decay_factor(mut self, factor: f64) -> Self {
+        assert!(factor >= 0.0);
+        self.decay_factor = factor;
+        self
+    }
+    /// The multiplier for the weight decay of the layer's columns.
+    pub fn decay_factor(&self) -> f64 {
+        self.decay_factor
+    }
+    /// If the layer uses whitening, see [`with_whitening`](#method.with_whitening).
+    pub fn whitened(&self) -> bool {
+        self.whitening.is_some()
+    }
+    /// The column weights of the whitening metric, i.e. the inverse standard deviations, if the layer uses whitening.
+    pub fn whitening_weights(&self) -> Option<&[f64]> {
+        self.whitening.as_ref().map(|w| w.weights())
+    }
     /// Creates a new continuous layer.
     pub fn cont(ncols: usize, weight: f64) -> Self {
         Self::new(ncols, weight, false, Metric::Euclidean)
@@ -115,6 +375,11 @@ impl Layer {
     pub fn ncols(&self) -> usize {
         self.ncols
     }
+    /// Sets the weight of the layer.
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
     /// The weight of the layer.
     pub fn weight(&self) -> f64 {
         self.weight
@@ -123,10 +388,56 @@ impl Layer {
     pub fn categorical(&self) -> bool {
         self.categorical
     }
-    /// The layer's distance metric.
+    /// The layer's built-in distance metric.
     pub fn metric(&self) -> &Metric {
         &self.metric
     }
+    /// If the layer has a user-defined distance metric.
+    pub fn has_custom_metric(&self) -> bool {
+        self.custom_metric.is_some()
+    }
+    /// The distance metric used for the layer. The user-defined metric if present,
+    /// the whitening metric if set, the built-in one otherwise.
+    pub fn distance_metric(&self) -> &dyn DistanceMetric {
+        match (&self.custom_metric, &self.whitening) {
+            (Some(metric), _) => metric.as_ref(),
+            (None, Some(metric)) => metric,
+            (None, None) => &self.metric,
+        }
+    }
+}
+
+/// Interpretation of the neighborhood radius.
+///
+/// The radius is the distance on the SOM grid at which the neighborhood function reaches its scale distance,
+/// e.g. the standard deviation for `Neighborhood::Gauss`, or the distance of zero weight for the other neighborhoods.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RadiusMode {
+    /// Radius in grid units (cells).
+    Absolute,
+    /// Radius as a fraction of the SOM's larger dimension, `max(nrows, ncols)`.
+    Fraction,
+}
+impl Default for RadiusMode {
+    fn default() -> Self {
+        RadiusMode::Absolute
+    }
+}
+impl FromStr for RadiusMode {
+    type Err = ParseEnumError;
+    /// Parse a string to a `RadiusMode`.
+    ///
+    /// Accepts `"abs" | "frac"`.
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "abs" => Ok(RadiusMode::Absolute),
+            "frac" => Ok(RadiusMode::Fraction),
+            _ => Err(ParseEnumError(format!(
+                "Not a radius mode: {}. Must be one of (abs|frac)",
+                str
+            ))),
+        }
+    }
 }
 
 /// Decay functions for learing parameters.
@@ -186,6 +497,39 @@ impl DecayParam {
             function: DecayFunction::Exponential,
         }
     }
+    /// Creates a learning parameter from start and end value and decay function,
+    /// and returns an error if it is invalid. See [`validate`](#method.validate).
+    pub fn checked(start: f64, end: f64, function: DecayFunction) -> Result<Self, DataTypeError> {
+        let param = Self::new(start, end, function);
+        param.validate()?;
+        Ok(param)
+    }
+    /// Checks the parameter for validity.
+    ///
+    /// Returns an error for non-finite start or end values, and for exponential decay with a start or end value
+    /// of zero or below, where the decay rate is not defined.
+    pub fn validate(&self) -> Result<(), DataTypeError> {
+        if !self.start.is_finite() || !self.end.is_finite() {
+            return Err(DataTypeError(format!(
+                "Decay parameter values must be finite, got start {} and end {}",
+                self.start, self.end
+            )));
+        }
+        if let DecayFunction::Exponential = self.function {
+            if self.start <= 0.0 || self.end <= 0.0 {
+                return Err(DataTypeError(format!(
+                    "Exponential decay requires start and end values > 0, got start {} and end {}",
+                    self.start, self.end
+                )));
+            }
+        }
+        Ok(())
+    }
+    /// If the end value is larger than the start value, i.e. the parameter grows instead of decaying.
+    /// This is almost always a mistake, e.g. swapped start and end values.
+    pub fn is_growing(&self) -> bool {
+        self.end > self.start
+    }
     /// Get the parameter's value for the given training epoch.
     pub fn get(&self, epoch: u32, max_epochs: u32) -> f64 {
         match self.function {
@@ -201,6 +545,112 @@ impl DecayParam {
     }
 }
 
+/// Early stopping of training when the monitored error, e.g. the
+/// [quantization error](struct.Som.html#method.quantization_error), does not improve any more.
+pub struct EarlyStopping {
+    patience: usize,
+    min_delta: f64,
+    best: f64,
+    wait: usize,
+}
+impl EarlyStopping {
+    /// Creates a new early stopping criterion.
+    /// Stops after `patience` consecutive checks without an improvement of more than `min_delta`.
+    pub fn new(patience: usize, min_delta: f64) -> Self {
+        EarlyStopping {
+            patience,
+            min_delta,
+            best: std::f64::MAX,
+            wait: 0,
+        }
+    }
+    /// Updates with the latest error. Returns `true` if training should stop.
+    pub fn update(&mut self, error: f64) -> bool {
+        if error < self.best - self.min_delta {
+            self.best = error;
+            self.wait = 0;
+        } else {
+            self.wait += 1;
+        }
+        self.wait >= self.patience
+    }
+    /// The best error so far.
+    pub fn best(&self) -> f64 {
+        self.best
+    }
+}
+
+/// Version of the SOM serialization format, see [`Som::save_to_json`](struct.Som.html#method.save_to_json).
+/// Incremented on incompatible changes of [`Som`](struct.Som.html) or [`SomParams`](struct.SomParams.html).
+pub const SOM_FORMAT_VERSION: u32 = 1;
+
+/// Metadata of a training run, for storing alongside a SOM in JSON output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingMetadata {
+    /// Path of the training data file.
+    pub data_file: String,
+    /// Number of rows of the training data.
+    pub data_rows: usize,
+    /// Number of columns of the training data.
+    pub data_cols: usize,
+    /// Size of the SOM as (rows, cols).
+    pub size: (usize, usize),
+    /// Number of training epochs.
+    pub epochs: u32,
+    /// Neighborhood function.
+    pub neighborhood: Neighborhood,
+    /// Learning rate.
+    pub alpha: DecayParam,
+    /// Neighborhood radius.
+    pub radius: DecayParam,
+    /// Weight decay.
+    pub decay: DecayParam,
+    /// Quantization error of the training data after training.
+    pub quantization_error: f64,
+    /// Time of creation, in seconds since the UNIX epoch.
+    pub timestamp: u64,
+}
+
+impl TrainingMetadata {
+    /// Collects metadata for a trained SOM and its training data, with the current time as timestamp.
+    pub fn new(data_file: &str, som: &Som, data: &DataFrame) -> Self {
+        let params = som.params();
+        TrainingMetadata {
+            data_file: data_file.to_string(),
+            data_rows: data.nrows(),
+            data_cols: data.ncols(),
+            size: som.size(),
+            epochs: som.total_epochs(),
+            neighborhood: params.neighborhood.clone(),
+            alpha: params.alpha.clone(),
+            radius: params.radius.clone(),
+            decay: params.decay.clone(),
+            quantization_error: som.quantization_error(data),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Serialization bundle of a SOM and de-normalization parameters, for writing.
+#[derive(Serialize)]
+struct SomBundleRef<'a> {
+    version: u32,
+    som: &'a Som,
+    denorm: &'a [LinearTransform],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a TrainingMetadata>,
+}
+
+/// Serialization bundle of a SOM and de-normalization parameters, for reading.
+#[derive(Deserialize)]
+struct SomBundle {
+    som: Som,
+    denorm: Vec<LinearTransform>,
+}
+
 /// Super-SOM core type.
 #[derive(Serialize, Deserialize)]
 #[allow(dead_code)]
@@ -211,27 +661,171 @@ pub struct Som {
     weights: DataFrame,
     params: SomParams,
     epoch: u32,
-    #[serde(skip_serializing)]
+    #[serde(default)]
+    extra_epochs: u32,
+    #[serde(skip_serializing, default = "Som::empty_distances")]
     distances_matrix: DataFrame,
+    #[serde(skip, default = "StdRng::from_entropy")]
+    rng: StdRng,
+    #[serde(skip)]
+    tracked_sample: Option<Vec<f64>>,
+    #[serde(skip)]
+    trajectory: Vec<(usize, usize)>,
+    #[serde(skip)]
+    win_frequencies: Vec<f64>,
+    #[serde(skip)]
+    decay_target: Option<Vec<f64>>,
 }
 
 #[allow(dead_code)]
 impl Som {
     /// Creates a new SOM or Super-SOM
     pub fn new(names: &[&str], nrows: usize, ncols: usize, params: SomParams) -> Self {
+        let rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
         let mut som = Som {
             dims: names.len(),
             nrows,
             ncols,
             weights: DataFrame::filled(nrows * ncols, names, 0.0),
-            distances_matrix: Self::calc_distance_matix(nrows, ncols),
+            distances_matrix: Self::create_distance_matrix(nrows, ncols, &params),
             params,
             epoch: 0,
+            extra_epochs: 0,
+            rng,
+            tracked_sample: None,
+            trajectory: vec![],
+            win_frequencies: vec![1.0 / (nrows * ncols) as f64; nrows * ncols],
+            decay_target: None,
         };
         som.init_weights();
+        debug_assert!(som.validate().is_ok());
         som
     }
 
+    /// Checks that the size and number of dimensions of the SOM are consistent with its weights.
+    fn validate(&self) -> Result<(), DataTypeError> {
+        if self.weights.nrows() != self.nrows * self.ncols {
+            return Err(DataTypeError(format!(
+                "Inconsistent SOM: {} weight rows for {} x {} units",
+                self.weights.nrows(),
+                self.nrows,
+                self.ncols
+            )));
+        }
+        if self.weights.ncols() != self.dims {
+            return Err(DataTypeError(format!(
+                "Inconsistent SOM: {} weight columns for {} dimensions",
+                self.weights.ncols(),
+                self.dims
+            )));
+        }
+        let start_columns = SomParams::calc_start_columns(&self.params.layers);
+        if self.params.start_columns != start_columns {
+            return Err(DataTypeError(format!(
+                "Inconsistent SOM: start columns {:?} for {} layers, expected {:?}",
+                self.params.start_columns,
+                self.params.layers.len(),
+                start_columns
+            )));
+        }
+        Ok(())
+    }
+
+    /// Resets the SOM for retraining with new parameters and size, e.g. for hyperparameter sweeps.
+    ///
+    /// Re-initializes weights and the training state. The distance matrix is only re-calculated
+    /// if the size or the grid metric changes, and re-used otherwise.
+    pub fn reset(&mut self, nrows: usize, ncols: usize, params: SomParams) {
+        if nrows != self.nrows
+            || ncols != self.ncols
+            || params.grid_metric != self.params.grid_metric
+            || params.on_the_fly_distances != self.params.on_the_fly_distances
+        {
+            self.distances_matrix = Self::create_distance_matrix(nrows, ncols, &params);
+        }
+        self.rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let names: Vec<_> = self.weights.columns().to_vec();
+        self.weights = DataFrame::filled(
+            nrows * ncols,
+            &names.iter().map(|n| &**n).collect::<Vec<_>>(),
+            0.0,
+        );
+        self.nrows = nrows;
+        self.ncols = ncols;
+        self.params = params;
+        self.epoch = 0;
+        self.extra_epochs = 0;
+        self.tracked_sample = None;
+        self.trajectory.clear();
+        self.reset_win_frequencies();
+        self.init_weights();
+    }
+
+    /// Serializes the SOM with de-normalization parameters and optional training metadata to JSON,
+    /// tagged with the format version.
+    pub fn save_to_json(
+        &self,
+        denorm: &[LinearTransform],
+        metadata: Option<&TrainingMetadata>,
+    ) -> Result<String, Box<dyn Error>> {
+        let bundle = SomBundleRef {
+            version: SOM_FORMAT_VERSION,
+            som: self,
+            denorm,
+            metadata,
+        };
+        Ok(serde_json::to_string_pretty(&bundle)?)
+    }
+
+    /// Deserializes a SOM with de-normalization parameters from JSON, as written by [`save_to_json`](#method.save_to_json).
+    ///
+    /// Returns an error if the format version is missing or not supported.
+    pub fn load_from_json(json: &str) -> Result<(Som, Vec<LinearTransform>), Box<dyn Error>> {
+        let value = Self::parse_versioned_json(json)?;
+        let bundle: SomBundle = serde_json::from_value(value)?;
+        let mut som = bundle.som;
+        som.validate().map_err(|err| err.to_string())?;
+        som.distances_matrix = Self::create_distance_matrix(som.nrows, som.ncols, &som.params);
+        som.reset_win_frequencies();
+        Ok((som, bundle.denorm))
+    }
+
+    /// Deserializes the training metadata from JSON, as written by [`save_to_json`](#method.save_to_json).
+    ///
+    /// Returns `None` if the JSON contains no metadata, and an error if the format version is missing or not supported.
+    pub fn load_metadata_from_json(json: &str) -> Result<Option<TrainingMetadata>, Box<dyn Error>> {
+        let mut value = Self::parse_versioned_json(json)?;
+        match value.get_mut("metadata") {
+            Some(meta) => Ok(Some(serde_json::from_value(meta.take())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses JSON and checks the format version.
+    fn parse_versioned_json(json: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(version) if version == SOM_FORMAT_VERSION as u64 => Ok(value),
+            Some(version) => Err(format!(
+                "Unsupported SOM format version {}, expected version {}",
+                version, SOM_FORMAT_VERSION
+            )
+            .into()),
+            None => Err("Unsupported SOM format: missing version".into()),
+        }
+    }
+
+    /// Placeholder for the distance matrix, which is not serialized.
+    fn empty_distances() -> DataFrame {
+        DataFrame::empty(&[])
+    }
+
     /// Returns a reference to the SOM's parameters.
     pub fn params(&self) -> &SomParams {
         &self.params
@@ -239,7 +833,7 @@ impl Som {
 
     /// Initialize weights. Called by the constructor automatically (may change!).
     pub fn init_weights(&mut self) {
-        let mut rng = rand::thread_rng();
+        let rng = &mut self.rng;
         let cols = self.weights.ncols();
         for row in self.weights.iter_rows_mut() {
             for col in &mut row[..cols] {
@@ -248,9 +842,82 @@ impl Som {
         }
     }
 
+    /// Initialize weights uniformly within the given (min, max) range of each column, e.g. from [`DataFrame::ranges`](../../data/struct.DataFrame.html#method.ranges).
+    ///
+    /// Columns with `NaN` ranges are initialized in [0, 1].
+    pub fn init_weights_in_ranges(&mut self, ranges: &[(f64, f64)]) {
+        assert_eq!(ranges.len(), self.weights.ncols());
+        let rng = &mut self.rng;
+        for row in self.weights.iter_rows_mut() {
+            for (col, (min, max)) in row.iter_mut().zip(ranges) {
+                *col = if min.is_nan() || max.is_nan() {
+                    rng.gen_range(0.0, 1.0)
+                } else if min < max {
+                    rng.gen_range(*min, *max)
+                } else {
+                    *min
+                };
+            }
+        }
+    }
+
+    /// Initialize weights of sparse columns near the column means of the training data, leaving other columns unchanged.
+    /// Use after other initialization methods.
+    ///
+    /// Columns with a fraction of `NA` values of at least `min_na_fraction` are initialized uniformly
+    /// within 10% of a standard deviation around the mean, as the few values barely move randomly initialized weights.
+    /// Columns without any values are left unchanged.
+    pub fn init_sparse_weights_at_means(&mut self, samples: &DataFrame, min_na_fraction: f64) {
+        assert_eq!(samples.ncols(), self.weights.ncols());
+        let means = samples.means();
+        let std_devs = samples.std_devs();
+        let sparse: Vec<_> = (0..samples.ncols())
+            .map(|col| {
+                let na = samples.iter_rows().filter(|row| row[col].is_nan()).count();
+                na < samples.nrows() && na as f64 >= min_na_fraction * samples.nrows() as f64
+            })
+            .collect();
+
+        let rng = &mut self.rng;
+        for row in self.weights.iter_rows_mut() {
+            for (col, v) in row.iter_mut().enumerate() {
+                if !sparse[col] {
+                    continue;
+                }
+                let spread = 0.1 * std_devs[col];
+                *v = if spread > 0.0 {
+                    rng.gen_range(means[col] - spread, means[col] + spread)
+                } else {
+                    means[col]
+                };
+            }
+        }
+    }
+
+    /// Creates the unit-to-unit distance matrix, or an empty placeholder if distances are calculated on the fly.
+    fn create_distance_matrix(nrows: usize, ncols: usize, params: &SomParams) -> DataFrame {
+        if params.on_the_fly_distances {
+            Self::empty_distances()
+        } else {
+            Self::calc_distance_matix(nrows, ncols, &params.grid_metric)
+        }
+    }
+
+    /// Distance between two units on the grid, from the distance matrix or calculated on the fly.
+    fn grid_distance(&self, from: usize, to: usize) -> f64 {
+        if self.params.on_the_fly_distances {
+            let (r1, c1) = self.to_row_col(from);
+            let (r2, c2) = self.to_row_col(to);
+            self.params
+                .grid_metric
+                .distance((r1 as f64, c1 as f64), (r2 as f64, c2 as f64))
+        } else {
+            *self.distances_matrix.get(from, to)
+        }
+    }
+
     /// Pre-calculates the unit-to-unit distance matrix.
-    fn calc_distance_matix(nrows: usize, ncols: usize) -> DataFrame {
-        let metric = Metric::Euclidean;
+    fn calc_distance_matix(nrows: usize, ncols: usize, metric: &GridMetric) -> DataFrame {
         let mut df = DataFrame::filled(nrows * ncols, &vec![""; nrows * ncols], 0.0);
         for r1 in 0..nrows {
             for c1 in 0..ncols {
@@ -261,7 +928,7 @@ impl Som {
                         df.set(
                             idx1,
                             idx2,
-                            metric.distance(&[r1 as f64, c1 as f64], &[r2 as f64, c2 as f64]),
+                            metric.distance((r1 as f64, c1 as f64), (r2 as f64, c2 as f64)),
                         );
                     }
                 }
@@ -274,9 +941,28 @@ impl Som {
         (index / self.ncols, index % self.ncols)
     }
     /// Returns the raw data index for (row, col).
+    ///
+    /// `row` and `col` must be within the bounds of the SOM, which is checked in debug builds only.
+    /// See [`to_index_checked`](#method.to_index_checked) for a checked variant.
     pub fn to_index(&self, row: i32, col: i32) -> usize {
+        debug_assert!(
+            row >= 0 && (row as usize) < self.nrows && col >= 0 && (col as usize) < self.ncols,
+            "Unit ({}, {}) out of bounds for SOM of size ({}, {})",
+            row,
+            col,
+            self.nrows,
+            self.ncols
+        );
         (row * self.ncols as i32 + col) as usize
     }
+    /// Returns the raw data index for (row, col), or `None` if (row, col) is out of the SOM's bounds.
+    pub fn to_index_checked(&self, row: i32, col: i32) -> Option<usize> {
+        if row < 0 || col < 0 || row as usize >= self.nrows || col as usize >= self.ncols {
+            None
+        } else {
+            Some(row as usize * self.ncols + col as usize)
+        }
+    }
     /// Returns a reference to the units weights data frame.
     pub fn weights(&self) -> &DataFrame {
         &self.weights
@@ -285,6 +971,36 @@ impl Som {
     pub fn weights_at(&self, row: usize, col: usize) -> &[f64] {
         self.weights.get_row(self.to_index(row as i32, col as i32))
     }
+    /// Returns a reference to the weights of the unit at the given raw data index.
+    pub fn weights_at_index(&self, index: usize) -> &[f64] {
+        self.weights.get_row(index)
+    }
+    /// Iterates over all units as (index, row, col, weights), in the order of the raw data index.
+    pub fn iter_units(&self) -> impl Iterator<Item = (usize, usize, usize, &[f64])> {
+        let ncols = self.ncols;
+        self.weights
+            .iter_rows()
+            .enumerate()
+            .map(move |(index, weights)| (index, index / ncols, index % ncols, weights))
+    }
+    /// Interpolates the weight of a column at a fractional grid position (row, col), e.g. for tooltips in zoomable viewers.
+    ///
+    /// Uses bilinear interpolation between the four surrounding units. Positions outside the grid are clamped to its edges.
+    pub fn interpolate(&self, column: usize, row: f64, col: f64) -> f64 {
+        let row = row.max(0.0).min((self.nrows - 1) as f64);
+        let col = col.max(0.0).min((self.ncols - 1) as f64);
+        let (r0, c0) = (row.floor() as usize, col.floor() as usize);
+        let (r1, c1) = (
+            cmp::min(r0 + 1, self.nrows - 1),
+            cmp::min(c0 + 1, self.ncols - 1),
+        );
+        let (fr, fc) = (row - r0 as f64, col - c0 as f64);
+
+        let value = |r: usize, c: usize| self.weights_at(r, c)[column];
+        let top = value(r0, c0) * (1.0 - fc) + value(r0, c1) * fc;
+        let bottom = value(r1, c0) * (1.0 - fc) + value(r1, c1) * fc;
+        top * (1.0 - fr) + bottom * fr
+    }
     /// The number of columns (width) of the SOM.
     pub fn ncols(&self) -> usize {
         self.ncols
@@ -298,15 +1014,26 @@ impl Som {
         (self.nrows, self.ncols)
     }
 
+    /// The neighborhood radius for the current epoch, in grid units.
+    pub fn radius(&self) -> f64 {
+        let radius = self
+            .params
+            .radius
+            .get(self.schedule_epoch(), self.params.epochs);
+        match self.params.radius_mode {
+            RadiusMode::Absolute => radius,
+            RadiusMode::Fraction => radius * cmp::max(self.nrows, self.ncols) as f64,
+        }
+    }
+
     /// Trains the SOM for one epoch. Updates learning parameters
     pub fn epoch(&mut self, samples: &DataFrame, count: Option<usize>) -> Option<()> {
-        if self.epoch >= self.params.epochs {
+        if self.epoch >= self.total_epochs() {
             return None;
         }
 
-        let mut rng = rand::thread_rng();
         let mut indices: Vec<_> = (0..samples.nrows()).collect();
-        rng.shuffle(&mut indices);
+        self.rng.shuffle(&mut indices);
 
         let cnt = cmp::min(count.unwrap_or_else(|| samples.nrows()), samples.nrows());
 
@@ -315,84 +1042,422 @@ impl Som {
             self.train(sample);
         }
 
-        self.decay_weights();
+        self.advance_epoch()
+    }
+
+    /// Trains the SOM for one epoch with weighted samples, e.g. unique rows with their counts
+    /// from [`DataFrame::deduplicate`](../../data/struct.DataFrame.html#method.deduplicate). Updates learning parameters.
+    ///
+    /// A sample with weight `w` moves units as far as `w` consecutive updates with the same best-matching unit,
+    /// i.e. by a fraction of `1 - (1 - h * alpha)^w`, with neighborhood weight `h`. For data with many duplicates,
+    /// this is much faster than training on all rows, with a very similar result.
+    ///
+    /// `count` limits the number of (unique) samples per epoch.
+    pub fn epoch_weighted(
+        &mut self,
+        samples: &DataFrame,
+        weights: &[f64],
+        count: Option<usize>,
+    ) -> Option<()> {
+        assert_eq!(samples.nrows(), weights.len());
+        if self.epoch >= self.total_epochs() {
+            return None;
+        }
+
+        let mut indices: Vec<_> = (0..samples.nrows()).collect();
+        self.rng.shuffle(&mut indices);
+
+        let cnt = cmp::min(count.unwrap_or_else(|| samples.nrows()), samples.nrows());
+
+        for idx in indices.iter().take(cnt) {
+            let sample = samples.get_row(*idx);
+            self.train_weighted(sample, weights[*idx]);
+        }
+
+        self.advance_epoch()
+    }
+
+    /// Trains the SOM for a single sample, using the current epoch's learning parameters.
+    /// For online learning, together with [`advance_epoch`](#method.advance_epoch).
+    pub fn train_sample(&mut self, sample: &[f64]) {
+        assert_eq!(sample.len(), self.dims);
+        self.train(sample);
+    }
+
+    /// Finishes the current epoch: applies weight decay and advances learning parameters.
+    /// Returns `None` if all epochs are done already.
+    pub fn advance_epoch(&mut self) -> Option<()> {
+        if self.epoch >= self.total_epochs() {
+            return None;
+        }
+
+        if self.params.decay_enabled {
+            self.decay_weights();
+        }
+
+        if let Some(sample) = &self.tracked_sample {
+            let (nearest, _) = self.nearest(sample);
+            let row_col = self.to_row_col(nearest);
+            self.trajectory.push(row_col);
+        }
 
         self.epoch += 1;
 
         Some(())
     }
 
-    /// Decays unit weights.
-    fn decay_weights(&mut self) {
-        let means = self.weights.means();
-        let cols = self.weights.ncols();
-        let decay = self.params.decay.get(self.epoch, self.params.epochs);
-        for row in self.weights.iter_rows_mut() {
-            for c in 0..cols {
-                let v = row[c];
-                let m = means[c];
-                row[c] = v - decay * (v - m);
-            }
-        }
+    /// The current training epoch, i.e. the number of finished epochs.
+    pub fn current_epoch(&self) -> u32 {
+        self.epoch
     }
 
-    /// Trains the SOM for a single sample.
-    fn train(&mut self, sample: &[f64]) {
-        let params = &self.params;
-        let (nearest, _) = if params.layers.is_empty() {
-            nn::nearest_neighbor(sample, &self.weights)
-        } else if params.layers.len() == 1 {
-            if params.layers[0].categorical {
-                nn::nearest_neighbor_tanimoto(sample, &self.weights)
-            } else {
-                nn::nearest_neighbor(sample, &self.weights)
-            }
-        } else {
-            nn::nearest_neighbor_xyf(sample, &self.weights, &params.layers)
-        };
-        let (row, col) = self.to_row_col(nearest);
+    /// The total number of training epochs, including epochs added by [`extend_training`](#method.extend_training).
+    pub fn total_epochs(&self) -> u32 {
+        self.params.epochs + self.extra_epochs
+    }
 
-        let alpha = self.params.alpha.get(self.epoch, self.params.epochs);
-        let radius = self.params.radius.get(self.epoch, self.params.epochs);
-        let neigh = &self.params.neighborhood;
-        let radius_inv = 1.0 / radius;
-        let search_rad = radius * neigh.radius();
-        let search_rad_i = search_rad.floor() as i32;
-        //let search_rad_sq = search_rad.powi(2);
+    /// Extends training by the given number of epochs, e.g. to continue training after all epochs are done.
+    ///
+    /// Learning parameters of the additional epochs stay at the end values of their schedules.
+    pub fn extend_training(&mut self, epochs: u32) {
+        self.extra_epochs += epochs;
+    }
 
-        let r_min = cmp::max(0, row as i32 - search_rad_i);
-        let r_max = cmp::min(self.nrows as i32 - 1, row as i32 + search_rad_i);
-        let c_min = cmp::max(0, col as i32 - search_rad_i);
-        let c_max = cmp::min(self.ncols as i32 - 1, col as i32 + search_rad_i);
+    /// The epoch for evaluating learning parameters. Clamped to the last scheduled epoch, for extended training.
+    fn schedule_epoch(&self) -> u32 {
+        cmp::min(self.epoch, self.params.epochs.saturating_sub(1))
+    }
 
-        for r in r_min..=r_max {
-            for c in c_min..=c_max {
-                let index = self.to_index(r, c);
-                let dist = *self.distances_matrix.get(nearest, index) as f64;
-                if dist <= search_rad {
-                    let weight = neigh.weight(radius_inv * dist);
-                    for (i, smp) in sample.iter().enumerate().take(self.dims) {
-                        if !smp.is_nan() {
-                            let value = self.weights.get_mut(index, i);
-                            *value += weight * alpha * (smp - *value);
+    /// Tracks the best-matching unit of a sample at the end of each epoch, for diagnostics. Does not affect training.
+    /// Resets the trajectory.
+    pub fn track_sample(&mut self, sample: Vec<f64>) {
+        assert_eq!(sample.len(), self.dims);
+        self.tracked_sample = Some(sample);
+        self.trajectory.clear();
+    }
+
+    /// Returns the trajectory of the tracked sample's best-matching unit as (row, col), one entry per epoch.
+    pub fn tracked_trajectory(&self) -> &[(usize, usize)] {
+        &self.trajectory
+    }
+
+    /// Smooths the weights on the grid, as a post-processing step after training.
+    ///
+    /// In each iteration, each unit moves half-way towards the mean weights of its (up to 4) direct grid neighbors.
+    /// Units without data (dead units) then sit between their neighbors. `NA` values are ignored.
+    pub fn smooth(&mut self, iterations: u32) {
+        const STRENGTH: f64 = 0.5;
+        let offsets = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+        let cols = self.weights.ncols();
+        for _ in 0..iterations {
+            let mut smoothed =
+                DataFrame::filled(self.weights.nrows(), &self.weights.columns_ref_vec(), 0.0);
+            for index in 0..self.weights.nrows() {
+                let (r, c) = self.to_row_col(index);
+                let neighbors: Vec<_> = offsets
+                    .iter()
+                    .filter_map(|(dr, dc)| self.to_index_checked(r as i32 + dr, c as i32 + dc))
+                    .collect();
+                for col in 0..cols {
+                    let v = *self.weights.get(index, col);
+                    let mut sum = 0.0;
+                    let mut count = 0;
+                    for n in &neighbors {
+                        let vn = *self.weights.get(*n, col);
+                        if !vn.is_nan() {
+                            sum += vn;
+                            count += 1;
                         }
                     }
+                    let value = if v.is_nan() || count == 0 {
+                        v
+                    } else {
+                        v + STRENGTH * (sum / count as f64 - v)
+                    };
+                    smoothed.set(index, col, value);
                 }
             }
+            self.weights = smoothed;
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::calc::neighborhood::Neighborhood;
-    use crate::data::DataFrame;
-    use crate::map::som::{DecayParam, Som, SomParams};
-    use rand::Rng;
+    /// Calculates the quantization error, i.e. the mean distance of samples to their best-matching units.
+    pub fn quantization_error(&self, samples: &DataFrame) -> f64 {
+        let sum: f64 = samples
+            .iter_rows()
+            .map(|sample| self.nearest(sample).1)
+            .sum();
+        sum / samples.nrows() as f64
+    }
 
-    #[test]
-    fn create_som() {
-        let params = SomParams::simple(
+    /// Calculates the topographic error, i.e. the fraction of samples for which the best-matching
+    /// and second-best-matching units are not adjacent on the grid (including diagonal neighbors).
+    pub fn topographic_error(&self, samples: &DataFrame) -> f64 {
+        if self.weights.nrows() < 2 {
+            return 0.0;
+        }
+        let errors = samples
+            .iter_rows()
+            .filter(|sample| {
+                let mut best = (std::usize::MAX, std::f64::MAX);
+                let mut second = (std::usize::MAX, std::f64::MAX);
+                for (idx, unit) in self.weights.iter_rows().enumerate() {
+                    let dist = self.unit_distance(sample, unit);
+                    if dist < best.1 {
+                        second = best;
+                        best = (idx, dist);
+                    } else if dist < second.1 {
+                        second = (idx, dist);
+                    }
+                }
+                let (r1, c1) = self.to_row_col(best.0);
+                let (r2, c2) = self.to_row_col(second.0);
+                (r1 as i32 - r2 as i32).abs() > 1 || (c1 as i32 - c2 as i32).abs() > 1
+            })
+            .count();
+        errors as f64 / samples.nrows() as f64
+    }
+
+    /// Counts the samples mapped to each unit, i.e. for which the unit is the best-matching unit.
+    /// Units with a count of zero are dead units.
+    pub fn hit_counts(&self, samples: &DataFrame) -> Vec<usize> {
+        let mut counts = vec![0; self.weights.nrows()];
+        for sample in samples.iter_rows() {
+            counts[self.nearest(sample).0] += 1;
+        }
+        counts
+    }
+
+    /// Calculates the alignment of two SOMs of the same size and columns, e.g. for checking training stability across seeds.
+    ///
+    /// The alignment is the mean distance between corresponding units in weight space, using the same distance as for
+    /// finding the best-matching unit. As maps trained with different seeds may be mirrored or rotated,
+    /// the minimum over all grid symmetries is returned (flips and 180° rotation, plus transpositions for square maps).
+    /// Low values mean similar maps.
+    ///
+    /// Returns an error if the SOMs differ in size or columns.
+    pub fn alignment(&self, other: &Som) -> Result<f64, DataTypeError> {
+        if self.size() != other.size() {
+            return Err(DataTypeError(format!(
+                "Can't align SOMs of different size: {:?} and {:?}",
+                self.size(),
+                other.size()
+            )));
+        }
+        if self.weights.columns() != other.weights.columns() {
+            return Err(DataTypeError(
+                "Can't align SOMs with different columns".to_string(),
+            ));
+        }
+
+        let (nrows, ncols) = (self.nrows, self.ncols);
+        let symmetry = |sym: usize, r: usize, c: usize| match sym {
+            0 => (r, c),
+            1 => (nrows - 1 - r, c),
+            2 => (r, ncols - 1 - c),
+            3 => (nrows - 1 - r, ncols - 1 - c),
+            4 => (c, r),
+            5 => (ncols - 1 - c, r),
+            6 => (c, nrows - 1 - r),
+            _ => (ncols - 1 - c, nrows - 1 - r),
+        };
+        let num_symmetries = if nrows == ncols { 8 } else { 4 };
+
+        let units = self.weights.nrows() as f64;
+        Ok((0..num_symmetries)
+            .map(|sym| {
+                self.iter_units()
+                    .map(|(_, r, c, unit)| {
+                        let (r2, c2) = symmetry(sym, r, c);
+                        self.unit_distance(unit, other.weights_at(r2, c2))
+                    })
+                    .sum::<f64>()
+                    / units
+            })
+            .fold(std::f64::MAX, f64::min))
+    }
+
+    /// Distance between a sample and a unit, as used for finding the best-matching unit.
+    fn unit_distance(&self, sample: &[f64], unit: &[f64]) -> f64 {
+        if self.params.layers.is_empty() {
+            Metric::Euclidean.distance(sample, unit)
+        } else {
+            nn::distance_xyf(sample, unit, &self.params.layers, std::f64::MAX)
+        }
+    }
+
+    /// Sets a fixed target for weight decay (mean reversion), e.g. the column means of the normalized training data.
+    /// By default, weights decay towards the current column means of the weights in each epoch.
+    ///
+    /// Columns with a `NaN` target are not decayed.
+    pub fn set_decay_target(&mut self, target: Vec<f64>) {
+        assert_eq!(target.len(), self.dims);
+        self.decay_target = Some(target);
+    }
+
+    /// Returns the fixed target for weight decay, if set.
+    pub fn decay_target(&self) -> Option<&[f64]> {
+        self.decay_target.as_deref()
+    }
+
+    /// Returns the units' win frequencies, as tracked by the conscience mechanism.
+    /// See [`SomParams::with_conscience`](struct.SomParams.html#method.with_conscience).
+    pub fn win_frequencies(&self) -> &[f64] {
+        &self.win_frequencies
+    }
+
+    /// Resets the units' win frequencies to the uniform rate.
+    pub fn reset_win_frequencies(&mut self) {
+        let units = self.nrows * self.ncols;
+        self.win_frequencies = vec![1.0 / units as f64; units];
+    }
+
+    /// Decays unit weights towards the column means. Columns with a mean of `NaN` are left unchanged.
+    fn decay_weights(&mut self) {
+        let means = match &self.decay_target {
+            Some(target) => target.clone(),
+            None => self.weights.means(),
+        };
+        let cols = self.weights.ncols();
+        let decay = self
+            .params
+            .decay
+            .get(self.schedule_epoch(), self.params.epochs);
+        let decays: Vec<_> = if self.params.layers.is_empty() {
+            vec![decay; cols]
+        } else {
+            self.params
+                .layers
+                .iter()
+                .flat_map(|lay| std::iter::repeat(decay * lay.decay_factor).take(lay.ncols))
+                .collect()
+        };
+        for row in self.weights.iter_rows_mut() {
+            for c in 0..cols {
+                let m = means[c];
+                if m.is_nan() {
+                    continue;
+                }
+                let v = row[c];
+                row[c] = v - decays[c] * (v - m);
+            }
+        }
+    }
+
+    /// Finds the best-matching unit for a sample.
+    /// # Returns
+    /// (index, distance)
+    fn nearest(&self, sample: &[f64]) -> (usize, f64) {
+        let params = &self.params;
+        if params.layers.is_empty() {
+            nn::nearest_neighbor(sample, &self.weights)
+        } else {
+            nn::nearest_neighbor_xyf(sample, &self.weights, &params.layers)
+        }
+    }
+
+    /// Finds the best-matching unit for a sample, biased by the conscience mechanism.
+    /// Updates the units' win frequencies.
+    fn nearest_conscience(&mut self, sample: &[f64], beta: f64, gamma: f64) -> usize {
+        let units = self.nrows * self.ncols;
+        if self.win_frequencies.len() != units {
+            self.reset_win_frequencies();
+        }
+        let rate = 1.0 / units as f64;
+
+        let mut min_dist = std::f64::MAX;
+        let mut min_idx: usize = 0;
+        for (idx, (unit, freq)) in self
+            .weights
+            .iter_rows()
+            .zip(&self.win_frequencies)
+            .enumerate()
+        {
+            let dist = self.unit_distance(sample, unit) - gamma * (rate - freq);
+            if dist < min_dist {
+                min_dist = dist;
+                min_idx = idx;
+            }
+        }
+
+        for (idx, freq) in self.win_frequencies.iter_mut().enumerate() {
+            let win = if idx == min_idx { 1.0 } else { 0.0 };
+            *freq += beta * (win - *freq);
+        }
+        min_idx
+    }
+
+    /// Trains the SOM for a single sample.
+    fn train(&mut self, sample: &[f64]) {
+        self.train_weighted(sample, 1.0);
+    }
+
+    /// Trains the SOM for a sample with the given weight, equivalent to `weight` consecutive updates.
+    fn train_weighted(&mut self, sample: &[f64], sample_weight: f64) {
+        let nearest = match self.params.conscience {
+            Some((beta, gamma)) => self.nearest_conscience(sample, beta, gamma),
+            None => self.nearest(sample).0,
+        };
+        let (row, col) = self.to_row_col(nearest);
+
+        let alpha = self
+            .params
+            .alpha
+            .get(self.schedule_epoch(), self.params.epochs);
+        let radius = self.radius();
+        let neigh = self.params.neighborhood_fn(self.schedule_epoch());
+        let min_weight = self.params.min_weight;
+        let radius_inv = 1.0 / radius;
+        let search_rad = radius * neigh.radius();
+        let search_rad_i = search_rad.floor() as i32;
+        //let search_rad_sq = search_rad.powi(2);
+
+        let r_min = cmp::max(0, row as i32 - search_rad_i);
+        let r_max = cmp::min(self.nrows as i32 - 1, row as i32 + search_rad_i);
+        let c_min = cmp::max(0, col as i32 - search_rad_i);
+        let c_max = cmp::min(self.ncols as i32 - 1, col as i32 + search_rad_i);
+
+        for r in r_min..=r_max {
+            for c in c_min..=c_max {
+                let index = self.to_index(r, c);
+                let dist = self.grid_distance(nearest, index);
+                if dist <= search_rad {
+                    let weight = neigh.weight(radius_inv * dist);
+                    if weight < min_weight {
+                        continue;
+                    }
+                    let rate = if sample_weight == 1.0 {
+                        weight * alpha
+                    } else {
+                        1.0 - (1.0 - weight * alpha).max(0.0).powf(sample_weight)
+                    };
+                    for (i, smp) in sample.iter().enumerate().take(self.dims) {
+                        if !smp.is_nan() {
+                            let value = self.weights.get_mut(index, i);
+                            *value += rate * (smp - *value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::calc::metric::{GridMetric, Metric};
+    use crate::calc::neighborhood::{Neighborhood, NeighborhoodFn};
+    use crate::calc::norm::LinearTransform;
+    use crate::data::DataFrame;
+    use crate::map::som::{
+        DecayFunction, DecayParam, EarlyStopping, Layer, RadiusMode, Som, SomParams,
+        TrainingMetadata, SOM_FORMAT_VERSION,
+    };
+    use rand::Rng;
+
+    #[test]
+    fn create_som() {
+        let params = SomParams::simple(
             100,
             Neighborhood::Gauss,
             DecayParam::lin(0.2, 0.01),
@@ -403,6 +1468,93 @@ mod test {
         assert_eq!(som.distances_matrix.get(0, 8), &8.0_f64.sqrt());
     }
 
+    #[test]
+    fn grid_metrics() {
+        let create = |metric: GridMetric| {
+            let params = SomParams::simple(
+                100,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(1.0, 0.5),
+                DecayParam::lin(0.2, 0.001),
+            )
+            .with_grid_metric(metric);
+            Som::new(&["A", "B", "C"], 3, 3, params)
+        };
+        let som = create(GridMetric::Euclidean);
+        assert_eq!(som.distances_matrix.get(0, 8), &8.0_f64.sqrt());
+        let som = create(GridMetric::Manhattan);
+        assert_eq!(som.distances_matrix.get(0, 8), &4.0);
+        let som = create(GridMetric::Chebyshev);
+        assert_eq!(som.distances_matrix.get(0, 8), &2.0);
+    }
+
+    #[test]
+    fn index_checked() {
+        let params = SomParams::simple(
+            100,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::lin(0.2, 0.001),
+        );
+        let som = Som::new(&["A", "B", "C"], 3, 4, params);
+        assert_eq!(som.to_index(2, 3), 11);
+        assert_eq!(som.to_index_checked(2, 3), Some(11));
+        assert_eq!(som.to_index_checked(-1, 0), None);
+        assert_eq!(som.to_index_checked(0, 4), None);
+        assert_eq!(som.to_index_checked(3, 0), None);
+    }
+
+    #[test]
+    fn init_in_ranges() {
+        let params = SomParams::simple(
+            100,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::lin(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A", "B", "C"], 4, 4, params);
+        let ranges = [(-5.0, -2.0), (10.0, 100.0), (3.0, 3.0)];
+        som.init_weights_in_ranges(&ranges);
+
+        for row in som.weights().iter_rows() {
+            for (v, (min, max)) in row.iter().zip(&ranges) {
+                assert!(v >= min && v <= max);
+            }
+        }
+    }
+
+    #[test]
+    fn init_sparse_at_means() {
+        let params = SomParams::simple(
+            100,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::lin(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A", "B"], 4, 4, params);
+        let before = som.weights().copy_column(0);
+
+        let nan = std::f64::NAN;
+        let rows: Vec<_> = (0..20)
+            .map(|i| match i {
+                0 => vec![0.0, 5.0],
+                1 => vec![0.5, 6.0],
+                _ => vec![i as f64 / 20.0, nan],
+            })
+            .collect();
+        let data = DataFrame::from_rows(&["A", "B"], &rows);
+        som.init_sparse_weights_at_means(&data, 0.5);
+
+        for (row, before) in som.weights().iter_rows().zip(before) {
+            assert_eq!(row[0], before);
+            assert!((row[1] - 5.5).abs() <= 0.1 * 0.5_f64.sqrt());
+        }
+    }
+
     #[test]
     fn train_step() {
         let params = SomParams::simple(
@@ -448,6 +1600,1097 @@ mod test {
         }*/
     }
 
+    #[test]
+    fn partial_epoch() {
+        let cols = ["A", "B"];
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&cols);
+        for _i in 0..5 {
+            data.push_row(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+        }
+
+        let create = || {
+            let params = SomParams::simple(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(3.0, 0.5),
+                DecayParam::exp(0.2, 0.001),
+            )
+            .with_seed(Some(7));
+            Som::new(&cols, 6, 6, params)
+        };
+
+        // More samples requested than available: all rows are used
+        let mut som_all = create();
+        let mut som_more = create();
+        let mut som_less = create();
+        som_all.epoch(&data, None);
+        som_more.epoch(&data, Some(20));
+        som_less.epoch(&data, Some(2));
+        assert_eq!(som_all.weights().data(), som_more.weights().data());
+        assert_ne!(som_all.weights().data(), som_less.weights().data());
+    }
+
+    #[test]
+    fn seeded_training() {
+        let cols = ["A", "B"];
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&cols);
+        for _i in 0..50 {
+            data.push_row(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+        }
+
+        let create = || {
+            let params = SomParams::simple(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(3.0, 0.5),
+                DecayParam::exp(0.2, 0.001),
+            )
+            .with_seed(Some(42));
+            Som::new(&cols, 6, 6, params)
+        };
+        let mut som1 = create();
+        let mut som2 = create();
+        assert_eq!(som1.weights().data(), som2.weights().data());
+
+        while let Some(()) = som1.epoch(&data, None) {}
+        while let Some(()) = som2.epoch(&data, None) {}
+        assert_eq!(som1.weights().data(), som2.weights().data());
+    }
+
+    #[test]
+    fn neighborhood_schedule() {
+        let params = SomParams::simple(
+            100,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::lin(0.2, 0.001),
+        );
+        assert_eq!(params.neighborhood(99), &Neighborhood::Gauss);
+
+        let params = params.with_neighborhood_schedule(0.75, Neighborhood::Triangular);
+        assert_eq!(params.neighborhood(0), &Neighborhood::Gauss);
+        assert_eq!(params.neighborhood(74), &Neighborhood::Gauss);
+        assert_eq!(params.neighborhood(75), &Neighborhood::Triangular);
+        assert_eq!(params.neighborhood(99), &Neighborhood::Triangular);
+    }
+
+    #[test]
+    fn track_sample() {
+        let cols = ["A", "B"];
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(3.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&cols, 6, 6, params);
+
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&cols);
+        for _i in 0..50 {
+            data.push_row(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+        }
+
+        som.track_sample(data.get_row(0).to_vec());
+        let mut epochs = 0;
+        while let Some(()) = som.epoch(&data, None) {
+            epochs += 1;
+        }
+
+        assert_eq!(som.tracked_trajectory().len(), epochs);
+        for (r, c) in som.tracked_trajectory() {
+            assert!(*r < som.nrows() && *c < som.ncols());
+        }
+    }
+
+    #[test]
+    fn decay_nan_column() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::lin(0.5, 0.5),
+        );
+        let mut som = Som::new(&["A", "B", "C"], 3, 3, params);
+        for row in 0..som.weights.nrows() {
+            som.weights.set(row, 0, row as f64);
+            som.weights.set(row, 2, std::f64::NAN);
+        }
+        let before = som.weights.copy_column(0);
+
+        som.decay_weights();
+
+        let mean = before.iter().sum::<f64>() / before.len() as f64;
+        for (row, v) in before.iter().enumerate() {
+            let expected = v - 0.5 * (v - mean);
+            assert!((som.weights.get(row, 0) - expected).abs() < 0.000001);
+            assert!(som.weights.get(row, 2).is_nan());
+        }
+    }
+
+    #[test]
+    fn decay_disabled() {
+        let cols = ["A", "B"];
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::lin(0.5, 0.5),
+        )
+        .with_decay_enabled(false);
+        let mut som = Som::new(&cols, 4, 4, params);
+        let data = DataFrame::from_rows(&cols, &[vec![0.5, 0.5]]);
+
+        let before = som.weights().data().to_vec();
+        som.epoch(&data, Some(0));
+        assert_eq!(som.weights().data(), &before[..]);
+    }
+
+    #[test]
+    fn train_online() {
+        let params = SomParams::simple(
+            5,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(3.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A", "B"], 6, 6, params);
+
+        let mut rng = rand::thread_rng();
+        for epoch in 0..5 {
+            assert_eq!(som.current_epoch(), epoch);
+            for _i in 0..20 {
+                som.train_sample(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+            }
+            assert_eq!(som.advance_epoch(), Some(()));
+        }
+        assert_eq!(som.current_epoch(), 5);
+        assert_eq!(som.advance_epoch(), None);
+        assert_eq!(som.current_epoch(), 5);
+    }
+
+    #[test]
+    fn recompute_start_columns() {
+        let params = || {
+            SomParams::xyf(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(3.0, 0.5),
+                DecayParam::exp(0.2, 0.001),
+                vec![Layer::cont(3, 0.5), Layer::cat(2, 0.3), Layer::cont(1, 0.2)],
+            )
+        };
+        let mut edited = params();
+        assert_eq!(edited.start_columns(), &[0, 3, 5]);
+        edited.recompute_start_columns();
+        assert_eq!(edited.start_columns(), params().start_columns());
+
+        edited.layers_mut().push(Layer::cont(4, 0.1));
+        edited.recompute_start_columns();
+        assert_eq!(edited.start_columns(), &[0, 3, 5, 6]);
+        assert_eq!(edited.column_range(3), 6..10);
+
+        edited.layers_mut().remove(1);
+        edited.recompute_start_columns();
+        assert_eq!(edited.start_columns(), &[0, 3, 4]);
+
+        let mut simple = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(3.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        simple.recompute_start_columns();
+        assert_eq!(simple.start_columns(), &[0]);
+
+        // stale start columns are detected
+        let mut stale = params();
+        stale.layers_mut().push(Layer::cont(1, 0.1));
+        let som = Som {
+            params: stale,
+            ..Som::new(&["A", "B", "C", "D", "E", "F", "G"], 2, 2, params())
+        };
+        assert!(som.validate().is_err());
+    }
+
+    #[test]
+    fn validate_decay_param() {
+        assert!(DecayParam::lin(0.2, 0.01).validate().is_ok());
+        assert!(DecayParam::lin(0.0, 0.0).validate().is_ok());
+        assert!(DecayParam::exp(0.2, 0.001).validate().is_ok());
+
+        assert!(DecayParam::exp(0.0, 0.001).validate().is_err());
+        assert!(DecayParam::exp(0.2, 0.0).validate().is_err());
+        assert!(DecayParam::lin(std::f64::NAN, 0.1).validate().is_err());
+        assert!(DecayParam::checked(0.0, 0.1, DecayFunction::Exponential).is_err());
+        assert!(DecayParam::checked(0.2, 0.1, DecayFunction::Exponential).is_ok());
+
+        assert!(!DecayParam::lin(0.2, 0.01).is_growing());
+        assert!(!DecayParam::lin(0.5, 0.5).is_growing());
+        assert!(DecayParam::exp(0.01, 0.2).is_growing());
+    }
+
+    #[test]
+    fn on_the_fly_distances() {
+        let params = |on_the_fly| {
+            SomParams::simple(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(3.0, 0.5),
+                DecayParam::exp(0.2, 0.001),
+            )
+            .with_seed(Some(1))
+            .with_grid_metric(GridMetric::Manhattan)
+            .with_on_the_fly_distances(on_the_fly)
+        };
+
+        let mut data = DataFrame::empty(&["A", "B"]);
+        let mut rng = rand::thread_rng();
+        for _i in 0..50 {
+            data.push_row(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+        }
+
+        let mut som = Som::new(&["A", "B"], 5, 4, params(false));
+        let mut som_fly = Som::new(&["A", "B"], 5, 4, params(true));
+        assert_eq!(som.distances_matrix.nrows(), 20);
+        assert_eq!(som_fly.distances_matrix.nrows(), 0);
+
+        while let Some(()) = som.epoch(&data, None) {}
+        while let Some(()) = som_fly.epoch(&data, None) {}
+
+        assert_eq!(som.weights().data(), som_fly.weights().data());
+    }
+
+    #[test]
+    fn extend_training() {
+        let params = SomParams::simple(
+            5,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(3.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A", "B"], 6, 6, params);
+
+        let mut data = DataFrame::empty(&["A", "B"]);
+        let mut rng = rand::thread_rng();
+        for _i in 0..20 {
+            data.push_row(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+        }
+
+        while let Some(()) = som.epoch(&data, None) {}
+        assert_eq!(som.current_epoch(), 5);
+        assert_eq!(som.total_epochs(), 5);
+        let radius = som.radius();
+
+        som.extend_training(3);
+        assert_eq!(som.total_epochs(), 8);
+        let before = som.weights().get_row(0).to_vec();
+        for _i in 0..3 {
+            assert_eq!(som.epoch(&data, None), Some(()));
+            assert_eq!(som.radius(), radius);
+        }
+        assert_eq!(som.epoch(&data, None), None);
+        assert_eq!(som.current_epoch(), 8);
+        assert_ne!(som.weights().get_row(0), &before[..]);
+    }
+
+    #[test]
+    fn single_layer_tanimoto_continuous() {
+        let params = SomParams::xyf(
+            5,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            vec![Layer::new(3, 1.0, true, Metric::TanimotoContinuous)],
+        );
+        let mut som = Som::new(&["A", "B", "C"], 1, 2, params);
+        som.weights = DataFrame::from_rows(
+            &["A", "B", "C"],
+            &[vec![0.4, 0.6, 0.0], vec![1.0, 0.0, 1.0]],
+        );
+
+        // thresholded Tanimoto would select unit 1
+        let sample = [0.6, 0.4, 0.0];
+        let (unit, dist) = som.nearest(&sample);
+        assert_eq!(unit, 0);
+        assert!((dist - (1.0 - 0.48 / 0.56)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn train_chain() {
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&["A"]);
+        for _i in 0..200 {
+            data.push_row(&[rng.gen_range(0.0, 1.0)]);
+        }
+
+        for (rows, cols) in &[(1, 20), (20, 1)] {
+            let params = SomParams::simple(
+                100,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(10.0, 0.5),
+                DecayParam::lin(0.0, 0.0),
+            )
+            .with_decay_enabled(false)
+            .with_seed(Some(1));
+            let mut som = Som::new(&["A"], *rows, *cols, params);
+
+            assert_eq!(som.distances_matrix.get(0, 19), &19.0);
+
+            while let Some(()) = som.epoch(&data, None) {}
+
+            let weights = som.weights().copy_column(0);
+            for pair in weights.windows(2) {
+                assert!((pair[1] - pair[0]).abs() < 0.25);
+            }
+        }
+    }
+
+    #[test]
+    fn conscience() {
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&["A", "B"]);
+        for i in 0..200 {
+            let center = if i % 2 == 0 { 0.15 } else { 0.85 };
+            data.push_row(&[
+                rng.gen_range(center - 0.05, center + 0.05),
+                rng.gen_range(center - 0.05, center + 0.05),
+            ]);
+        }
+
+        let dead_units = |conscience: bool| {
+            // Winner-takes-all, to isolate the effect of the conscience
+            let params = SomParams::simple(
+                20,
+                Neighborhood::Triangular,
+                DecayParam::lin(0.2, 0.05),
+                DecayParam::lin(0.5, 0.5),
+                DecayParam::lin(0.0, 0.0),
+            )
+            .with_decay_enabled(false)
+            .with_seed(Some(3));
+            let params = if conscience {
+                params.with_conscience(0.01, 2.0)
+            } else {
+                params
+            };
+            let mut som = Som::new(&["A", "B"], 4, 4, params);
+            while let Some(()) = som.epoch(&data, None) {}
+
+            let mut hits = vec![0; 16];
+            for row in data.iter_rows() {
+                hits[som.nearest(row).0] += 1;
+            }
+            hits.iter().filter(|h| **h == 0).count()
+        };
+
+        assert!(dead_units(true) < dead_units(false));
+    }
+
+    #[test]
+    fn conscience_frequencies() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        )
+        .with_conscience(0.1, 1.0);
+        let mut som = Som::new(&["A", "B"], 3, 3, params);
+        assert!(som
+            .win_frequencies()
+            .iter()
+            .all(|f| (f - 1.0 / 9.0).abs() < 1e-12));
+
+        som.train_sample(&[0.5, 0.5]);
+        let sum: f64 = som.win_frequencies().iter().sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+        assert_eq!(
+            som.win_frequencies()
+                .iter()
+                .filter(|f| **f > 1.0 / 9.0)
+                .count(),
+            1
+        );
+
+        som.reset_win_frequencies();
+        assert!(som
+            .win_frequencies()
+            .iter()
+            .all(|f| (f - 1.0 / 9.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn weights_at_index() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::lin(0.2, 0.001),
+        );
+        let som = Som::new(&["A", "B", "C"], 3, 4, params);
+        for index in 0..12 {
+            let (row, col) = som.to_row_col(index);
+            assert_eq!(som.weights_at_index(index), som.weights_at(row, col));
+        }
+    }
+
+    #[test]
+    fn column_range() {
+        let params = SomParams::xyf(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            vec![
+                Layer::cont(3, 0.5),
+                Layer::cat(1, 0.25),
+                Layer::cat(2, 0.25),
+            ],
+        );
+        assert_eq!(params.column_range(0), 0..3);
+        assert_eq!(params.column_range(1), 3..4);
+        assert_eq!(params.column_range(2), 4..6);
+    }
+
+    #[test]
+    fn layer_of_column() {
+        let params = SomParams::xyf(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            vec![
+                Layer::cont(3, 0.5),
+                Layer::cat(1, 0.25),
+                Layer::cat(2, 0.25),
+            ],
+        );
+        let expected = [0, 0, 0, 1, 2, 2];
+        for (col, layer) in expected.iter().enumerate() {
+            assert_eq!(params.layer_of_column(col), *layer);
+        }
+
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        assert_eq!(params.layer_of_column(5), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn layer_of_column_out_of_range() {
+        let params = SomParams::xyf(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            vec![Layer::cont(3, 0.5), Layer::cat(2, 0.5)],
+        );
+        params.layer_of_column(5);
+    }
+
+    #[test]
+    fn quantization_error() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        )
+        .with_seed(Some(5));
+        let mut som = Som::new(&["A", "B"], 4, 4, params);
+
+        let mut rng = rand::thread_rng();
+        let mut data = DataFrame::empty(&["A", "B"]);
+        for _i in 0..100 {
+            data.push_row(&[rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)]);
+        }
+
+        let qe_start = som.quantization_error(&data);
+        while let Some(()) = som.epoch(&data, None) {}
+        let qe_end = som.quantization_error(&data);
+        assert!(qe_end < qe_start);
+
+        let units = DataFrame::from_rows(
+            &["A", "B"],
+            &[
+                som.weights().get_row(0).to_vec(),
+                som.weights().get_row(5).to_vec(),
+            ],
+        );
+        assert_eq!(som.quantization_error(&units), 0.0);
+    }
+
+    #[test]
+    fn smooth() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A"], 6, 6, params);
+        for index in 0..36 {
+            som.weights.set(index, 0, 0.5);
+        }
+        som.weights.set(som.to_index(2, 3), 0, 1.0);
+        som.weights.set(som.to_index(4, 1), 0, 0.0);
+
+        // Variance of the U-matrix, i.e. of mean distances to grid neighbors
+        let u_variance = |som: &Som| {
+            let offsets = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+            let u: Vec<_> = (0..36)
+                .map(|index| {
+                    let (r, c) = som.to_row_col(index);
+                    let dists: Vec<_> = offsets
+                        .iter()
+                        .filter_map(|(dr, dc)| som.to_index_checked(r as i32 + dr, c as i32 + dc))
+                        .map(|n| (som.weights.get(index, 0) - som.weights.get(n, 0)).abs())
+                        .collect();
+                    dists.iter().sum::<f64>() / dists.len() as f64
+                })
+                .collect();
+            let mean = u.iter().sum::<f64>() / u.len() as f64;
+            u.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / u.len() as f64
+        };
+
+        let var_before = u_variance(&som);
+        som.smooth(1);
+        let var_1 = u_variance(&som);
+        som.smooth(2);
+        let var_3 = u_variance(&som);
+
+        assert!(var_1 < var_before);
+        assert!(var_3 < var_1);
+    }
+
+    #[test]
+    fn reset() {
+        let create_params = |seed| {
+            SomParams::simple(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(2.0, 0.5),
+                DecayParam::lin(0.2, 0.001),
+            )
+            .with_seed(Some(seed))
+        };
+        let mut som = Som::new(&["A", "B"], 4, 5, create_params(1));
+        let data = DataFrame::filled(10, &["A", "B"], 0.5);
+        som.epoch(&data, None);
+
+        let matrix_ptr = som.distances_matrix.data().as_ptr();
+        let matrix = som.distances_matrix.data().to_vec();
+
+        som.reset(4, 5, create_params(2));
+        assert_eq!(som.current_epoch(), 0);
+        assert_eq!(som.distances_matrix.data().as_ptr(), matrix_ptr);
+        assert_eq!(som.distances_matrix.data(), &matrix[..]);
+
+        let fresh = Som::new(&["A", "B"], 4, 5, create_params(2));
+        assert_eq!(som.weights().data(), fresh.weights().data());
+
+        som.reset(3, 3, create_params(2));
+        assert_eq!(som.size(), (3, 3));
+        assert_eq!(som.weights().nrows(), 9);
+        assert_eq!(som.distances_matrix.nrows(), 9);
+        assert_eq!(som.distances_matrix.get(0, 8), &8.0_f64.sqrt());
+    }
+
+    #[test]
+    fn save_load_json() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let som = Som::new(&["A", "B"], 3, 4, params);
+        let denorm = vec![
+            LinearTransform::new(2.0, 1.0),
+            LinearTransform::new(1.0, 0.0),
+        ];
+
+        let json = som.save_to_json(&denorm, None).unwrap();
+        let (som2, denorm2) = Som::load_from_json(&json).unwrap();
+        assert_eq!(som2.size(), (3, 4));
+        assert_eq!(som2.weights().data(), som.weights().data());
+        assert_eq!(som2.distances_matrix.data(), som.distances_matrix.data());
+        assert_eq!(denorm2.len(), 2);
+        assert_eq!(denorm2[0].scale(), 2.0);
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["version"] = 999.into();
+        let result = Som::load_from_json(&value.to_string());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            format!(
+                "Unsupported SOM format version 999, expected version {}",
+                SOM_FORMAT_VERSION
+            )
+        );
+
+        let unversioned = serde_json::to_string(&(&som, &denorm)).unwrap();
+        assert!(Som::load_from_json(&unversioned).is_err());
+    }
+
+    #[test]
+    fn iter_units() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let som = Som::new(&["A", "B"], 3, 4, params);
+
+        let units: Vec<_> = som.iter_units().collect();
+        assert_eq!(units.len(), 12);
+        let (index, row, col, weights) = units[11];
+        assert_eq!((index, row, col), (11, 2, 3));
+        assert_eq!(weights, som.weights_at(2, 3));
+        for (index, row, col, _) in units {
+            assert_eq!(som.to_row_col(index), (row, col));
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        let params = || {
+            SomParams::simple(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(2.0, 0.5),
+                DecayParam::exp(0.2, 0.001),
+            )
+        };
+        let som = Som::new(&["A", "B"], 3, 4, params());
+        assert!(som.alignment(&som).unwrap().abs() < 1e-12);
+
+        let mut mirrored = Som::new(&["A", "B"], 3, 4, params());
+        for (index, r, c, _) in som.iter_units() {
+            let mirrored_index = mirrored.to_index(r as i32, 3 - c as i32);
+            for col in 0..2 {
+                mirrored
+                    .weights
+                    .set(mirrored_index, col, *som.weights.get(index, col));
+            }
+        }
+        assert!(som.alignment(&mirrored).unwrap().abs() < 1e-12);
+
+        let other = Som::new(&["A", "B"], 3, 4, params().with_seed(Some(1)));
+        assert!(som.alignment(&other).unwrap() > 0.0);
+
+        let smaller = Som::new(&["A", "B"], 3, 3, params());
+        assert!(som.alignment(&smaller).is_err());
+        let renamed = Som::new(&["A", "C"], 3, 4, params());
+        assert!(som.alignment(&renamed).is_err());
+    }
+
+    #[test]
+    fn layer_decay_factor() {
+        let decayed_classes = |factor: f64| {
+            let params = SomParams::xyf(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(2.0, 0.5),
+                DecayParam::lin(0.5, 0.5),
+                vec![
+                    Layer::cont(1, 0.5),
+                    Layer::cat(2, 0.5).with_decay_factor(factor),
+                ],
+            );
+            let mut som = Som::new(&["A", "B:x", "B:y"], 2, 2, params);
+            for i in 0..4 {
+                som.weights.set(i, 0, i as f64);
+                som.weights.set(i, 1, if i < 3 { 1.0 } else { 0.0 });
+                som.weights.set(i, 2, if i < 3 { 0.0 } else { 1.0 });
+            }
+            som.decay_weights();
+            assert_eq!(*som.weights.get(0, 0), 0.75);
+            som.weights.get_row(3)[1..].to_vec()
+        };
+
+        assert_eq!(decayed_classes(0.0), vec![0.0, 1.0]);
+        assert_eq!(decayed_classes(1.0), vec![0.375, 0.625]);
+    }
+
+    #[test]
+    fn decay_target() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::lin(0.5, 0.5),
+        );
+        let mut som = Som::new(&["A", "B"], 3, 3, params);
+        assert!(som.decay_target().is_none());
+        som.set_decay_target(vec![5.0, std::f64::NAN]);
+        let before = som.weights().copy_column(1);
+
+        for _ in 0..20 {
+            som.decay_weights();
+        }
+        for (row, before) in som.weights().iter_rows().zip(before) {
+            assert!((row[0] - 5.0).abs() < 1e-4);
+            assert_eq!(row[1], before);
+        }
+    }
+
+    #[test]
+    fn epoch_weighted() {
+        let params = || {
+            SomParams::simple(
+                1,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.3, 0.3),
+                DecayParam::lin(1.0, 1.0),
+                DecayParam::lin(0.0, 0.0),
+            )
+            .with_seed(Some(1))
+        };
+
+        // a single sample with weight 3 is equivalent to three consecutive updates
+        let data = DataFrame::from_rows(&["A", "B"], &[vec![0.2, 0.8]]);
+        let mut weighted = Som::new(&["A", "B"], 3, 3, params());
+        let mut repeated = Som::new(&["A", "B"], 3, 3, params());
+        weighted.epoch_weighted(&data, &[3.0], None).unwrap();
+        for _ in 0..3 {
+            repeated.train_sample(&[0.2, 0.8]);
+        }
+        for (w, r) in weighted
+            .weights()
+            .data()
+            .iter()
+            .zip(repeated.weights().data())
+        {
+            assert!((w - r).abs() < 1e-12);
+        }
+
+        let repeats = DataFrame::from_rows(
+            &["A", "B"],
+            &[vec![0.2, 0.8], vec![0.9, 0.1], vec![0.2, 0.8]],
+        );
+        let (unique, counts) = repeats.deduplicate();
+        let counts: Vec<_> = counts.iter().map(|c| *c as f64).collect();
+        let mut som = Som::new(&["A", "B"], 3, 3, params());
+        assert!(som.epoch_weighted(&unique, &counts, None).is_some());
+        assert!(som.epoch_weighted(&unique, &counts, None).is_none());
+    }
+
+    #[test]
+    fn interpolate() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A", "B"], 3, 4, params);
+        for index in 0..12 {
+            let (r, c) = som.to_row_col(index);
+            som.weights.set(index, 0, (10 * r + c) as f64);
+        }
+
+        for (_, r, c, weights) in som.iter_units() {
+            assert_eq!(som.interpolate(1, r as f64, c as f64), weights[1]);
+        }
+        assert!((som.interpolate(0, 0.5, 0.5) - 5.5).abs() < 1e-12);
+        assert!((som.interpolate(0, 1.25, 2.0) - 14.5).abs() < 1e-12);
+        assert_eq!(som.interpolate(0, -1.0, 10.0), 3.0);
+        assert_eq!(som.interpolate(0, 5.0, 3.0), 23.0);
+    }
+
+    #[test]
+    fn load_inconsistent_json() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let som = Som::new(&["A", "B"], 3, 4, params);
+        assert!(som.validate().is_ok());
+        let json = som.save_to_json(&[], None).unwrap();
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["som"]["nrows"] = 4.into();
+        let result = Som::load_from_json(&value.to_string());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Inconsistent SOM: 12 weight rows for 4 x 4 units"
+        );
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["som"]["dims"] = 3.into();
+        let result = Som::load_from_json(&value.to_string());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Inconsistent SOM: 2 weight columns for 3 dimensions"
+        );
+    }
+
+    #[test]
+    fn save_load_metadata() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A", "B"], 3, 4, params);
+        let data = DataFrame::from_rows(&["A", "B"], &[vec![0.1, 0.2], vec![0.8, 0.9]]);
+        while let Some(()) = som.epoch(&data, None) {}
+        let denorm = vec![
+            LinearTransform::new(1.0, 0.0),
+            LinearTransform::new(1.0, 0.0),
+        ];
+
+        let json = som.save_to_json(&denorm, None).unwrap();
+        assert!(Som::load_metadata_from_json(&json).unwrap().is_none());
+
+        let meta = TrainingMetadata::new("data/test.csv", &som, &data);
+        let json = som.save_to_json(&denorm, Some(&meta)).unwrap();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let fields = [
+            "data_file",
+            "data_rows",
+            "data_cols",
+            "size",
+            "epochs",
+            "neighborhood",
+            "alpha",
+            "radius",
+            "decay",
+            "quantization_error",
+            "timestamp",
+        ];
+        for field in fields.iter() {
+            assert!(value["metadata"].get(field).is_some(), "Missing {}", field);
+        }
+
+        let meta2 = Som::load_metadata_from_json(&json).unwrap().unwrap();
+        assert_eq!(meta2.data_file, "data/test.csv");
+        assert_eq!((meta2.data_rows, meta2.data_cols), (2, 2));
+        assert_eq!(meta2.size, (3, 4));
+        assert_eq!(meta2.epochs, 10);
+        assert_eq!(meta2.neighborhood, Neighborhood::Gauss);
+        assert!((meta2.quantization_error - som.quantization_error(&data)).abs() < 1e-12);
+        assert_eq!(meta2.timestamp, meta.timestamp);
+        assert!(meta2.timestamp > 0);
+
+        let (som2, _) = Som::load_from_json(&json).unwrap();
+        assert_eq!(som2.weights().data(), som.weights().data());
+    }
+
+    #[test]
+    fn topographic_error() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+        );
+        let mut som = Som::new(&["A"], 1, 3, params);
+        let samples = DataFrame::from_rows(&["A"], &[vec![0.4], vec![1.6]]);
+
+        for (i, v) in [0.0, 1.0, 2.0].iter().enumerate() {
+            som.weights.set(i, 0, *v);
+        }
+        assert_eq!(som.topographic_error(&samples), 0.0);
+        assert_eq!(som.hit_counts(&samples), vec![1, 0, 1]);
+
+        // Units 1 and 2 swapped: for the first sample, the second-best unit is not adjacent
+        for (i, v) in [0.0, 2.0, 1.0].iter().enumerate() {
+            som.weights.set(i, 0, *v);
+        }
+        assert_eq!(som.topographic_error(&samples), 0.5);
+        assert_eq!(som.hit_counts(&samples), vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn early_stopping() {
+        let mut stop = EarlyStopping::new(2, 0.1);
+        assert!(!stop.update(1.0));
+        assert!(!stop.update(0.5));
+        assert!(!stop.update(0.45));
+        assert!(!stop.update(0.3));
+        assert!(!stop.update(0.25));
+        assert!(stop.update(0.25));
+        assert_eq!(stop.best(), 0.3);
+    }
+
+    #[test]
+    fn radius_fraction() {
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Triangular,
+            DecayParam::lin(1.0, 1.0),
+            DecayParam::lin(0.5, 0.5),
+            DecayParam::lin(0.0, 0.0),
+        )
+        .with_radius_mode(RadiusMode::Fraction);
+        let mut som = Som::new(&["A"], 1, 20, params);
+        assert_eq!(som.radius(), 10.0);
+
+        for i in 0..20 {
+            som.weights.set(i, 0, 1.0);
+        }
+        som.weights.set(0, 0, 0.0);
+        som.train_sample(&[0.0]);
+
+        let updated = som.weights().data().iter().filter(|v| **v < 1.0).count();
+        assert_eq!(updated, 10);
+    }
+
+    #[test]
+    fn min_weight() {
+        let updated_units = |min_weight: f64| {
+            let params = SomParams::simple(
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(1.0, 1.0),
+                DecayParam::lin(2.0, 2.0),
+                DecayParam::lin(0.0, 0.0),
+            )
+            .with_min_weight(min_weight);
+            let mut som = Som::new(&["A"], 1, 20, params);
+            for i in 0..20 {
+                som.weights.set(i, 0, 1.0);
+            }
+            som.weights.set(0, 0, 0.0);
+            som.train_sample(&[0.0]);
+            som.weights().data().iter().filter(|v| **v < 1.0).count()
+        };
+
+        // radius 2 with search radius 3 * 2, and exp(-0.5 * (d / 2)²) >= 0.5 only for d <= 2
+        assert_eq!(updated_units(0.0), 7);
+        assert_eq!(updated_units(0.5), 3);
+    }
+
+    #[test]
+    fn single_layer_match_metric() {
+        let params = SomParams::xyf(
+            1,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            vec![Layer::new(3, 1.0, false, Metric::Match)],
+        );
+        let mut som = Som::new(&["A", "B", "C"], 1, 2, params);
+        som.weights = DataFrame::from_rows(
+            &["A", "B", "C"],
+            &[vec![0.0, 0.0, 3.0], vec![1.0, 1.0, 0.0]],
+        );
+
+        // Euclidean distance would select unit 1
+        let sample = [0.0, 0.0, 0.0];
+        assert_eq!(som.nearest(&sample), (0, 1.0 / 3.0));
+
+        let data = DataFrame::from_rows(&["A", "B", "C"], &[sample.to_vec()]);
+        assert_eq!(som.hit_counts(&data), vec![1, 0]);
+        assert_eq!(som.quantization_error(&data), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn single_layer_hamming() {
+        let params = SomParams::xyf(
+            1,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            vec![Layer::new(3, 1.0, false, Metric::Hamming)],
+        );
+        let mut som = Som::new(&["A", "B", "C"], 1, 2, params);
+        som.weights = DataFrame::from_rows(
+            &["A", "B", "C"],
+            &[vec![0.6, 0.4, 0.4], vec![1.0, 0.0, 0.55]],
+        );
+
+        // Euclidean distance would select unit 1
+        let sample = [1.0, 0.0, 0.0];
+        assert_eq!(som.nearest(&sample), (0, 0.0));
+        let data = DataFrame::from_rows(&["A", "B", "C"], &[sample.to_vec()]);
+        assert_eq!(som.hit_counts(&data), vec![1, 0]);
+    }
+
+    #[test]
+    fn custom_neighborhood() {
+        struct BoxKernel;
+        impl NeighborhoodFn for BoxKernel {
+            fn weight(&self, distance: f64) -> f64 {
+                if distance < 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            fn radius(&self) -> f64 {
+                1.0
+            }
+        }
+
+        let params = SomParams::simple(
+            10,
+            Neighborhood::Triangular,
+            DecayParam::lin(1.0, 1.0),
+            DecayParam::lin(3.0, 3.0),
+            DecayParam::lin(0.0, 0.0),
+        )
+        .with_custom_neighborhood(BoxKernel);
+        let mut som = Som::new(&["A"], 1, 20, params);
+
+        for i in 0..20 {
+            som.weights.set(i, 0, 1.0);
+        }
+        som.weights.set(0, 0, 0.0);
+        som.train_sample(&[0.0]);
+
+        let weights = som.weights().data();
+        assert!(weights[..3].iter().all(|v| *v == 0.0));
+        assert!(weights[3..].iter().all(|v| *v == 1.0));
+    }
+
     #[test]
     fn linear_decay() {
         let decay = DecayParam::lin(1.0, 0.1);
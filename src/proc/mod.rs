@@ -1,16 +1,24 @@
 //! Pre- and post-processing of SOM training data, SOM creation.
 
 use crate::calc::metric::Metric;
-use crate::calc::neighborhood::Neighborhood;
+use crate::calc::neighborhood::{Neighborhood, Neighbors};
 use crate::calc::nn;
 use crate::calc::norm;
 use crate::data::DataFrame;
 use crate::map::som::{DecayParam, Layer, Som, SomParams};
 use crate::DataTypeError;
-use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use csv::{Reader, ReaderBuilder, StringRecord, WriterBuilder};
+use flate2::read::GzDecoder;
+use rand::prelude::*;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cmp;
 use std::collections::HashSet;
 use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Seek, Write};
+use std::ops::Range;
 
 /// Layer definition for input tables.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -23,6 +31,14 @@ pub struct InputLayer {
     metric: Metric,
     norm: norm::Norm,
     scale: f64,
+    #[serde(default)]
+    centered: bool,
+    #[serde(default)]
+    whiten: bool,
+    #[serde(default)]
+    encoded_prefix: Option<String>,
+    #[serde(default)]
+    level_index: bool,
 }
 
 impl InputLayer {
@@ -46,6 +62,10 @@ impl InputLayer {
             metric,
             norm,
             scale: scale.unwrap_or(1.0),
+            centered: false,
+            whiten: false,
+            encoded_prefix: None,
+            level_index: false,
         }
     }
 
@@ -60,6 +80,10 @@ impl InputLayer {
             metric: Metric::Tanimoto,
             norm: norm::Norm::None,
             scale: 1.0,
+            centered: false,
+            whiten: false,
+            encoded_prefix: None,
+            level_index: false,
         }
     }
 
@@ -74,6 +98,57 @@ impl InputLayer {
             metric: Metric::Tanimoto,
             norm: norm::Norm::None,
             scale: 1.0,
+            centered: false,
+            whiten: false,
+            encoded_prefix: None,
+            level_index: false,
+        }
+    }
+
+    /// Creates a new categorical input layer definition from already one-hot encoded columns.
+    ///
+    /// All columns with names starting with `prefix` form the layer, in the order of the file header.
+    /// Values are read as numbers, without discovering levels. The level names are the column names without the prefix,
+    /// e.g. columns `color_red` and `color_blue` with prefix `color_` result in layer `color` with levels `red` and `blue`.
+    pub fn cat_encoded(prefix: &str, weight: f64) -> Self {
+        InputLayer {
+            names: vec![prefix.to_string()],
+            indices: None,
+            num_columns: None,
+            weight,
+            is_class: true,
+            metric: Metric::Tanimoto,
+            norm: norm::Norm::None,
+            scale: 1.0,
+            centered: false,
+            whiten: false,
+            encoded_prefix: Some(prefix.to_string()),
+            level_index: false,
+        }
+    }
+
+    /// Creates a new categorical input layer definition stored as a single column of level indices,
+    /// instead of one-hot encoded columns. Keeps the data and weights narrow for categories with many levels.
+    ///
+    /// Levels are indexed in sorted order. Distances use the match / no-match metric `Metric::Match`.
+    /// Unit weights are trained like continuous values, and are rounded to the nearest level index for distance calculation.
+    ///
+    /// The resulting layer is not treated as categorical, i.e. class-based methods like [`to_class`](struct.Processor.html#method.to_class)
+    /// are not available for it.
+    pub fn cat_index(name: &str, weight: f64) -> Self {
+        InputLayer {
+            names: vec![name.to_string()],
+            indices: None,
+            num_columns: None,
+            weight,
+            is_class: false,
+            metric: Metric::Match,
+            norm: norm::Norm::None,
+            scale: 1.0,
+            centered: false,
+            whiten: false,
+            encoded_prefix: None,
+            level_index: true,
         }
     }
 
@@ -88,6 +163,10 @@ impl InputLayer {
             metric: Metric::Euclidean,
             norm,
             scale: scale.unwrap_or(1.0),
+            centered: false,
+            whiten: false,
+            encoded_prefix: None,
+            level_index: false,
         }
     }
 
@@ -102,24 +181,173 @@ impl InputLayer {
             metric: Metric::Euclidean,
             norm: norm::Norm::Gauss,
             scale: 1.0,
+            centered: false,
+            whiten: false,
+            encoded_prefix: None,
+            level_index: false,
+        }
+    }
+
+    /// Sets the scale of the layer, applied after normalization. Default 1.0.
+    ///
+    /// E.g., a scale of 2.0 with `Norm::Unit` results in a normalized range of [0, 2].
+    /// Unlike the layer weight, which scales the layer's contribution to the fused distance,
+    /// the scale changes the data values, and thus distances within the layer.
+    ///
+    /// Categorical layers should keep the default scale, as the Tanimoto metric uses a threshold of 0.5.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        assert!(scale > 0.0);
+        self.scale = scale;
+        self
+    }
+
+    /// The scale of the layer, applied after normalization.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Sets whether a categorical layer is encoded centered, as +0.5 / -0.5 instead of 1.0 / 0.0. Default `false`.
+    ///
+    /// With centered encoding, categorical columns have the same scale as Gauss-normalized continuous columns,
+    /// so that they pull units similarly during training. De-normalization shifts values back to the 0 / 1 scale.
+    ///
+    /// As the Tanimoto and Hamming metrics use a fixed threshold of 0.5 for class membership, which assumes the 0 / 1 encoding,
+    /// these metrics are replaced by the Euclidean metric.
+    pub fn with_centered_encoding(mut self, centered: bool) -> Self {
+        assert!(self.is_class || !centered);
+        assert!(self.encoded_prefix.is_none() || !centered);
+        self.centered = centered;
+        if centered && (self.metric == Metric::Tanimoto || self.metric == Metric::Hamming) {
+            self.metric = Metric::Euclidean;
+        }
+        self
+    }
+
+    /// If the layer is categorical and encoded centered.
+    pub fn centered(&self) -> bool {
+        self.centered
+    }
+
+    /// Sets whether a continuous layer is whitened at distance time. Default `false`.
+    ///
+    /// With whitening, each column's contribution to the layer's distance is divided by the column's standard deviation
+    /// in the normalized training data. This is applied in addition to normalization,
+    /// and prevents columns with a large normalized spread from dominating the layer.
+    /// See [`Layer::with_whitening`](../map/som/struct.Layer.html#method.with_whitening).
+    pub fn with_whitening(mut self, whiten: bool) -> Self {
+        assert!(!self.is_class || !whiten);
+        assert!(!self.level_index || !whiten);
+        self.whiten = whiten;
+        self
+    }
+
+    /// If the layer is whitened at distance time.
+    pub fn whiten(&self) -> bool {
+        self.whiten
+    }
+
+    /// If the layer's levels are discovered and encoded when reading data, one-hot or as level indices,
+    /// i.e. not created by [`cat_encoded`](#method.cat_encoded) or as a continuous layer.
+    fn encodes_levels(&self) -> bool {
+        (self.is_class && self.encoded_prefix.is_none()) || self.level_index
+    }
+}
+
+/// SOM configuration for training multiple SOMs with [`Processor::create_soms`](struct.Processor.html#method.create_soms).
+#[derive(Clone, Debug)]
+pub struct SomConfig {
+    nrows: usize,
+    ncols: usize,
+    epochs: u32,
+    neighborhood: Neighborhood,
+    alpha: DecayParam,
+    radius: DecayParam,
+    decay: DecayParam,
+    seed: Option<u64>,
+}
+
+impl SomConfig {
+    /// Creates a new SOM configuration.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        nrows: usize,
+        ncols: usize,
+        epochs: u32,
+        neighborhood: Neighborhood,
+        alpha: DecayParam,
+        radius: DecayParam,
+        decay: DecayParam,
+        seed: Option<u64>,
+    ) -> Self {
+        SomConfig {
+            nrows,
+            ncols,
+            epochs,
+            neighborhood,
+            alpha,
+            radius,
+            decay,
+            seed,
         }
     }
 }
 
+/// Threshold for detecting outliers, see [`Processor::write_outliers`](struct.Processor.html#method.write_outliers).
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutlierThreshold {
+    /// Absolute distance to the nearest unit.
+    Distance(f64),
+    /// Quantile of the distances of all rows to their nearest units, in [0, 1].
+    Quantile(f64),
+}
+
 /// Csv file options
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CsvOptions {
     delimiter: u8,
     no_data: String,
+    #[serde(default)]
+    gzip: bool,
+    #[serde(default)]
+    fixed_widths: Option<Vec<usize>>,
+    #[serde(default)]
+    precision: Option<usize>,
+}
+
+impl CsvOptions {
+    /// Formats a value for output, with the configured number of decimal places. NaN is formatted as the no-data token.
+    fn format_value(&self, value: f64) -> String {
+        if value.is_nan() {
+            self.no_data.clone()
+        } else if let Some(precision) = self.precision {
+            format!("{:.*}", precision, value)
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+/// Label options, for reading labels from data files.
+#[derive(Clone, Debug)]
+struct LabelOptions {
+    columns: Vec<String>,
+    separator: String,
+    length: Option<usize>,
+    samples: Option<usize>,
+    seed: Option<u64>,
 }
 
+/// Default maximum number of cells (data rows × SOM units) for writing distance matrices.
+pub const DEFAULT_MAX_DISTANCE_MATRIX_SIZE: usize = 1_000_000;
+
 /// Builder for ['Processor'](struct.Processor.html).
 pub struct ProcessorBuilder {
     input_layers: Vec<InputLayer>,
     preserve: Vec<String>,
-    labels: Option<String>,
-    label_length: Option<usize>,
-    label_samples: Option<usize>,
+    labels: LabelOptions,
+    class_probabilities: bool,
+    max_distance_matrix_size: usize,
+    keep_source: bool,
     csv_options: CsvOptions,
 }
 impl ProcessorBuilder {
@@ -134,12 +362,22 @@ impl ProcessorBuilder {
         ProcessorBuilder {
             input_layers: layers.to_vec(),
             preserve: preserve.to_vec(),
-            labels: label.clone(),
-            label_length: *label_length,
-            label_samples: *label_samples,
+            labels: LabelOptions {
+                columns: label.iter().cloned().collect(),
+                separator: " ".to_string(),
+                length: *label_length,
+                samples: *label_samples,
+                seed: None,
+            },
+            class_probabilities: false,
+            max_distance_matrix_size: DEFAULT_MAX_DISTANCE_MATRIX_SIZE,
+            keep_source: false,
             csv_options: CsvOptions {
                 delimiter: b',',
                 no_data: "NA".to_string(),
+                gzip: false,
+                fixed_widths: None,
+                precision: None,
             },
         }
     }
@@ -148,22 +386,80 @@ impl ProcessorBuilder {
         self.csv_options.delimiter = delimiter;
         self
     }
+    /// Sets the delimiter to tab, for reading tab-separated files. Shorthand for `with_delimiter(b'\t')`.
+    pub fn tsv(self) -> Self {
+        self.with_delimiter(b'\t')
+    }
+    /// Reads fixed-width files instead of delimited files, with the given width of each column in characters.
+    /// Values are trimmed. Applies to the header line as well. Default: delimited files.
+    pub fn with_fixed_widths(mut self, widths: &[usize]) -> Self {
+        self.csv_options.fixed_widths = Some(widths.to_vec());
+        self
+    }
     /// Sets the no-data value for CSV files. Default 'NA'.
     pub fn with_no_data(mut self, no_data: &str) -> Self {
         self.csv_options.no_data = no_data.to_string();
         self
     }
+    /// Sets whether input files are gzip-compressed. Default `false`.
+    ///
+    /// Files with extension `.gz` are always read as gzip-compressed.
+    pub fn with_gzip_input(mut self, gzip: bool) -> Self {
+        self.csv_options.gzip = gzip;
+        self
+    }
+    /// Sets the number of decimal places for continuous values and distances in CSV output. Default: shortest exact representation.
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.csv_options.precision = Some(precision);
+        self
+    }
+    /// Sets multiple label columns, replacing the label column given in the constructor.
+    /// Per row, the values are concatenated with `separator` to a label. Truncation to the maximum label length
+    /// applies to the combined label.
+    pub fn with_label_columns(mut self, columns: &[&str], separator: &str) -> Self {
+        self.labels.columns = columns.iter().map(|c| c.to_string()).collect();
+        self.labels.separator = separator.to_string();
+        self
+    }
+    /// Sets the seed for sampling labels, for reproducible label selection (e.g. in figures). Default `None`, i.e. random.
+    ///
+    /// Typically, the same seed as for the SOM is used.
+    pub fn with_label_seed(mut self, seed: Option<u64>) -> Self {
+        self.labels.seed = seed;
+        self
+    }
+    /// Sets whether to write the per-class values of categorical layers in data output, instead of the class. Default `false`.
+    pub fn with_class_probabilities(mut self, class_probabilities: bool) -> Self {
+        self.class_probabilities = class_probabilities;
+        self
+    }
+    /// Sets the maximum number of cells (data rows × SOM units) for writing distance matrices.
+    /// Default [`DEFAULT_MAX_DISTANCE_MATRIX_SIZE`](constant.DEFAULT_MAX_DISTANCE_MATRIX_SIZE.html).
+    ///
+    /// See [`Processor::write_distance_matrix`](struct.Processor.html#method.write_distance_matrix).
+    pub fn with_max_distance_matrix_size(mut self, cells: usize) -> Self {
+        self.max_distance_matrix_size = cells;
+        self
+    }
+    /// Sets whether to keep the source data in memory, for adding layers later. Default `false`.
+    ///
+    /// See [`Processor::add_layer`](struct.Processor.html#method.add_layer).
+    pub fn with_keep_source(mut self, keep_source: bool) -> Self {
+        self.keep_source = keep_source;
+        self
+    }
     /// Builds a [`Processor`](struct.Processor.html) from the given data file.
     pub fn build_from_file(self, path: &str) -> Result<Processor, Box<dyn Error>> {
-        let proc = Processor::new(
+        let mut proc = Processor::new(
             self.input_layers,
             self.preserve,
             self.labels,
-            self.label_length,
-            self.label_samples,
             path,
             &self.csv_options,
+            self.keep_source,
         )?;
+        proc.class_probabilities = self.class_probabilities;
+        proc.max_distance_matrix_size = self.max_distance_matrix_size;
         Ok(proc)
     }
 }
@@ -179,33 +475,39 @@ pub struct Processor {
     preserve_columns: Vec<String>,
     #[serde(skip_serializing)]
     preserved: Vec<Vec<String>>,
-    label_column: Option<String>,
+    #[serde(default)]
+    label_columns: Vec<String>,
     #[serde(skip_serializing)]
     labels: Option<Vec<(usize, String)>>,
     norm: Vec<norm::Norm>,
     denorm: Vec<norm::LinearTransform>,
     scale: Vec<f64>,
     csv_options: CsvOptions,
+    #[serde(default)]
+    class_probabilities: bool,
+    #[serde(default = "Processor::default_max_distance_matrix_size")]
+    max_distance_matrix_size: usize,
+    /// Source data as CSV, and its delimiter. Kept only on request, for adding layers.
+    #[serde(skip)]
+    source: Option<(Vec<u8>, u8)>,
 }
 
 impl Processor {
     fn new(
         input_layers: Vec<InputLayer>,
         preserve: Vec<String>,
-        labels: Option<String>,
-        label_length: Option<usize>,
-        label_samples: Option<usize>,
+        labels: LabelOptions,
         path: &str,
         csv_options: &CsvOptions,
+        keep_source: bool,
     ) -> Result<Self, Box<dyn Error>> {
         Self::read_file(
             input_layers,
             preserve,
             labels,
-            label_length,
-            label_samples,
             path,
             csv_options,
+            keep_source,
         )
     }
 
@@ -221,6 +523,14 @@ impl Processor {
     pub fn input_layers(&self) -> &[InputLayer] {
         &self.input_layers
     }
+    /// Returns for each layer whether it is categorical, and the names of the source columns it is read from,
+    /// as opposed to the encoded column names in [`data`](#method.data).
+    pub fn input_layer_names(&self) -> Vec<(bool, Vec<String>)> {
+        self.input_layers
+            .iter()
+            .map(|inp| (inp.is_class, inp.names.clone()))
+            .collect()
+    }
     /// Return a reference to the applied normalizers.
     pub fn norm(&self) -> &[norm::Norm] {
         &self.norm
@@ -229,11 +539,165 @@ impl Processor {
     pub fn denorm(&self) -> &[norm::LinearTransform] {
         &self.denorm
     }
-    /// Return a reference the applies scalings (not used so far).
+    /// Return a reference to the applied scalings, one per column. See [`InputLayer::with_scale`](struct.InputLayer.html#method.with_scale).
     pub fn scale(&self) -> &[f64] {
         &self.scale
     }
 
+    /// Returns the normalized weights of all layers.
+    pub fn layer_weights(&self) -> Vec<f64> {
+        self.layers.iter().map(|l| l.weight()).collect()
+    }
+
+    /// Checks the ranges of the normalized data, as SOM weights are initialized and best trained in about [0, 1].
+    /// Further, checks for continuous layers with very different spreads, e.g. when mixing `Norm::None` and `Norm::Gauss`,
+    /// as the layer with the larger spread dominates distances regardless of layer weights.
+    ///
+    /// # Returns
+    /// A warning message for each column with a range far outside [0, 1], e.g. due to missing normalization,
+    /// and a warning if the mean standard deviations of continuous layers differ by more than a factor of 10.
+    /// Index-encoded categorical layers (see [`InputLayer::cat_index`](struct.InputLayer.html#method.cat_index))
+    /// and layers without any spread are ignored.
+    pub fn check_scaling(&self) -> Vec<String> {
+        let tolerance = 2.0;
+        // level indices of index-encoded categorical layers are not normalized
+        let index_encoded: Vec<_> = self
+            .layers
+            .iter()
+            .flat_map(|layer| {
+                std::iter::repeat(layer.metric() == &Metric::Match).take(layer.ncols())
+            })
+            .collect();
+        let mut warnings: Vec<_> = self
+            .data
+            .ranges()
+            .iter()
+            .zip(self.data.columns())
+            .zip(&index_encoded)
+            .filter(|(_, index)| !**index)
+            .map(|(range, _)| range)
+            .filter(|((min, max), _)| *min < -tolerance || *max > 1.0 + tolerance)
+            .map(|((min, max), name)| {
+                format!(
+                    "Column '{}' has a normalized range of [{}, {}], far outside [0, 1]. Consider normalization.",
+                    name, min, max
+                )
+            })
+            .collect();
+
+        let max_ratio = 10.0;
+        let std_devs = self.data.std_devs();
+        let mut spreads = Vec::new();
+        let mut start = 0;
+        for (index, layer) in self.layers.iter().enumerate() {
+            let sds: Vec<_> = std_devs[start..(start + layer.ncols())]
+                .iter()
+                .filter(|sd| !sd.is_nan())
+                .collect();
+            if !layer.categorical() && layer.metric() != &Metric::Match && !sds.is_empty() {
+                let spread = sds.iter().cloned().sum::<f64>() / sds.len() as f64;
+                // constant layers carry no spread to compare
+                if spread > 0.0 {
+                    spreads.push((index, spread));
+                }
+            }
+            start += layer.ncols();
+        }
+        let min = spreads.iter().min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let max = spreads.iter().max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let (Some((min_idx, min_sd)), Some((max_idx, max_sd))) = (min, max) {
+            if *max_sd > max_ratio * min_sd {
+                warnings.push(format!(
+                    "Layer {} has a much larger spread than layer {} (mean normalized standard deviation {} vs. {}), \
+                     and dominates distances regardless of layer weights. Check normalization of layers.",
+                    max_idx, min_idx, max_sd, min_sd
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Sets layer weights inversely proportional to the number of columns of each layer, normalized to a sum of 1.
+    /// Prevents layers with many columns from dominating the distance calculation.
+    ///
+    /// Affects only SOMs created afterwards.
+    pub fn auto_weight_layers(&mut self) {
+        let sum: f64 = self.layers.iter().map(|l| 1.0 / l.ncols() as f64).sum();
+        self.layers = self
+            .layers
+            .iter()
+            .map(|l| l.clone().with_weight(1.0 / (l.ncols() as f64 * sum)))
+            .collect();
+    }
+
+    /// Adds a layer, read from the source data kept in memory. Requires the processor to be built with
+    /// [`ProcessorBuilder::with_keep_source`](struct.ProcessorBuilder.html#method.with_keep_source).
+    ///
+    /// Extends the data, normalization and layer definitions. Layer weights are re-normalized from the
+    /// input layer weights, like when reading a file, which resets weights from
+    /// [`auto_weight_layers`](#method.auto_weight_layers).
+    ///
+    /// SOMs created before do not match the processor's layers any more. A new SOM must be created afterwards.
+    pub fn add_layer(&mut self, layer: InputLayer) -> Result<(), Box<dyn Error>> {
+        let (buffer, delimiter) = self.source.as_ref().ok_or(
+            "Adding layers requires the source data. See ProcessorBuilder::with_keep_source.",
+        )?;
+        let label_options = LabelOptions {
+            columns: vec![],
+            separator: " ".to_string(),
+            length: None,
+            samples: None,
+            seed: None,
+        };
+        let added = Self::read_csv(
+            ReaderBuilder::new()
+                .delimiter(*delimiter)
+                .from_reader(Cursor::new(buffer)),
+            vec![layer],
+            vec![],
+            label_options,
+            &self.csv_options,
+            "source data",
+        )?;
+        assert_eq!(added.data.nrows(), self.data.nrows());
+        if let Some(col) = added
+            .data
+            .columns()
+            .iter()
+            .find(|col| self.data.columns().contains(col))
+        {
+            return Err(format!("Column '{}' is already used by a layer", col).into());
+        }
+
+        let mut columns = self.data.columns_ref_vec();
+        columns.extend(added.data.columns().iter().map(|c| &**c));
+        let mut data = DataFrame::empty(&columns);
+        let mut row = Vec::with_capacity(columns.len());
+        for (row1, row2) in self.data.iter_rows().zip(added.data.iter_rows()) {
+            row.clear();
+            row.extend_from_slice(row1);
+            row.extend_from_slice(row2);
+            data.push_row(&row);
+        }
+        self.data = data;
+
+        self.norm.extend(added.norm);
+        self.denorm.extend(added.denorm);
+        self.scale.extend(added.scale);
+        self.input_layers.extend(added.input_layers);
+
+        let weight_scale = 1.0 / self.input_layers.iter().map(|l| l.weight).sum::<f64>();
+        self.layers = self
+            .layers
+            .iter()
+            .chain(added.layers.iter())
+            .zip(&self.input_layers)
+            .map(|(lay, inp)| lay.clone().with_weight(weight_scale * inp.weight))
+            .collect();
+
+        Ok(())
+    }
+
     pub fn labels(&self) -> Option<&[(usize, String)]> {
         match &self.labels {
             Some(lab) => Some(&lab),
@@ -242,26 +706,148 @@ impl Processor {
     }
 
     fn read_file(
-        mut input_layers: Vec<InputLayer>,
+        input_layers: Vec<InputLayer>,
         preserve_columns: Vec<String>,
-        label_column: Option<String>,
-        label_length: Option<usize>,
-        label_samples: Option<usize>,
+        label_options: LabelOptions,
         path: &str,
         csv_options: &CsvOptions,
+        keep_source: bool,
+    ) -> Result<Processor, Box<dyn Error>> {
+        let mut delimiter = csv_options.delimiter;
+
+        // Read to memory if required, as the file is read twice
+        let gzip = csv_options.gzip || path.ends_with(".gz");
+        let buffer = if let Some(widths) = &csv_options.fixed_widths {
+            let mut text = String::new();
+            if gzip {
+                GzDecoder::new(Self::open_file(path)?)
+                    .read_to_string(&mut text)
+                    .map_err(|err| Self::read_error(path, err))?;
+            } else {
+                Self::open_file(path)?
+                    .read_to_string(&mut text)
+                    .map_err(|err| Self::read_error(path, err))?;
+            }
+            delimiter = b',';
+            Some(Self::fixed_width_to_csv(&text, widths)?)
+        } else if gzip {
+            let mut buffer = Vec::new();
+            GzDecoder::new(Self::open_file(path)?)
+                .read_to_end(&mut buffer)
+                .map_err(|err| Self::read_error(path, err))?;
+            Some(buffer)
+        } else if keep_source {
+            let mut buffer = Vec::new();
+            Self::open_file(path)?
+                .read_to_end(&mut buffer)
+                .map_err(|err| Self::read_error(path, err))?;
+            Some(buffer)
+        } else {
+            None
+        };
+
+        let mut builder = ReaderBuilder::new();
+        builder.delimiter(delimiter);
+        let mut proc = match &buffer {
+            Some(buffer) => Self::read_csv(
+                builder.from_reader(Cursor::new(buffer)),
+                input_layers,
+                preserve_columns,
+                label_options,
+                csv_options,
+                path,
+            )?,
+            None => Self::read_csv(
+                builder.from_reader(Self::open_file(path)?),
+                input_layers,
+                preserve_columns,
+                label_options,
+                csv_options,
+                path,
+            )?,
+        };
+        if keep_source {
+            proc.source = buffer.map(|buffer| (buffer, delimiter));
+        }
+        Ok(proc)
+    }
+
+    /// Converts fixed-width text to comma-separated CSV, in memory.
+    fn fixed_width_to_csv(text: &str, widths: &[usize]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let chars: Vec<_> = line.chars().collect();
+            let mut start = 0;
+            let record: Vec<String> = widths
+                .iter()
+                .map(|w| {
+                    let end = cmp::min(start + w, chars.len());
+                    let field: String = chars[cmp::min(start, end)..end].iter().collect();
+                    start += w;
+                    field.trim().to_string()
+                })
+                .collect();
+            writer.write_record(&record)?;
+        }
+        Ok(writer.into_inner()?)
+    }
+
+    /// Opens a data file, with the path in the error message.
+    fn open_file(path: &str) -> Result<File, Box<dyn Error>> {
+        File::open(path)
+            .map_err(|err| format!("Unable to open data file '{}': {}", path, err).into())
+    }
+
+    /// Creates an error for a data file that can't be read, with the path in the message.
+    fn read_error(path: &str, err: impl std::fmt::Display) -> Box<dyn Error> {
+        format!("Unable to read data file '{}': {}", path, err).into()
+    }
+
+    fn read_csv<R: Read + Seek>(
+        mut reader: Reader<R>,
+        mut input_layers: Vec<InputLayer>,
+        preserve_columns: Vec<String>,
+        label_options: LabelOptions,
+        csv_options: &CsvOptions,
+        path: &str,
     ) -> Result<Processor, Box<dyn Error>> {
         let no_data = &csv_options.no_data;
 
-        // Read csv
-        let mut reader = ReaderBuilder::new()
-            .delimiter(csv_options.delimiter)
-            .from_path(path)
-            .unwrap();
-        let header: StringRecord = reader.headers().unwrap().clone();
+        let header: StringRecord = reader
+            .headers()
+            .map_err(|err| Self::read_error(path, err))?
+            .clone();
         let header: Vec<_> = header.iter().collect();
 
+        // check for duplicate column names
+        let mut seen = HashSet::<&str>::new();
+        let mut duplicates = Vec::<&str>::new();
+        for &name in header.iter() {
+            if !seen.insert(name) && !duplicates.contains(&name) {
+                duplicates.push(name);
+            }
+        }
+        if !duplicates.is_empty() {
+            return Err(format!(
+                "Duplicate column names in CSV header: '{}'",
+                duplicates.join("', '")
+            )
+            .into());
+        }
+
         // find column indices for layers
         for lay in input_layers.iter_mut() {
+            if let Some(prefix) = &lay.encoded_prefix {
+                let names: Vec<_> = header
+                    .iter()
+                    .filter(|n| n.starts_with(&prefix[..]))
+                    .map(|n| n.to_string())
+                    .collect();
+                if names.is_empty() {
+                    return Err(format!("No columns found with prefix '{}'", prefix).into());
+                }
+                lay.names = names;
+            }
             lay.indices = Some(
                 lay.names
                     .iter()
@@ -280,14 +866,14 @@ impl Processor {
         let categorical: Vec<_> = input_layers
             .iter()
             .enumerate()
-            .filter(|(_i, lay)| lay.is_class)
+            .filter(|(_i, lay)| lay.encodes_levels())
             .collect();
 
         // find unique levals of categorical layers
         let mut cat_levels: Vec<_> = vec![HashSet::<String>::new(); input_layers.len()];
         let start_pos = reader.position().clone();
         for record in reader.records() {
-            let rec = record?;
+            let rec = record.map_err(|err| Self::read_error(path, err))?;
             for (idx, lay) in categorical.iter() {
                 let v = rec.get(lay.indices.as_ref().unwrap()[0]).unwrap();
                 let levels = &mut cat_levels[*idx];
@@ -309,10 +895,20 @@ impl Processor {
 
         // determine number of output table columns for categorical layers
         for (cat, levels) in input_layers.iter_mut().zip(cat_levels.iter_mut()) {
-            if !levels.is_empty() {
-                cat.num_columns = Some(levels.len());
+            if cat.encodes_levels() {
+                if levels.is_empty() {
+                    return Err(format!(
+                        "Categorical layer '{}' has no levels: all values are no-data ('{}')",
+                        cat.names[0], no_data
+                    )
+                    .into());
+                }
+                cat.num_columns = Some(if cat.level_index { 1 } else { levels.len() });
             }
         }
+        if let Some(lay) = input_layers.iter().find(|l| l.num_columns == Some(0)) {
+            return Err(format!("Layer {:?} has no columns", lay.names).into());
+        }
 
         // create layer definitions
         let weight_scale = 1.0 / input_layers.iter().map(|l| l.weight).sum::<f64>();
@@ -326,7 +922,15 @@ impl Processor {
                 lay.is_class,
                 lay.metric.clone(),
             ));
-            if lay.is_class {
+            if let Some(prefix) = &lay.encoded_prefix {
+                let is_sep = |c: char| !c.is_alphanumeric();
+                let base = prefix.trim_end_matches(is_sep).to_string() + ":";
+                colnames.extend(
+                    lay.names
+                        .iter()
+                        .map(|n| base.clone() + n[prefix.len()..].trim_start_matches(is_sep)),
+                );
+            } else if lay.is_class {
                 let base = lay.names[0].clone() + ":";
                 let levels = &cat_levels[idx];
                 colnames.extend(levels.iter().map(|l| base.clone() + l));
@@ -347,27 +951,32 @@ impl Processor {
             .collect();
         let mut id_values = vec![Vec::<String>::new(); id_indices.len()];
 
-        // get label column
-        let (label_index, mut labels) = match &label_column {
-            Some(col) => (
-                Some(
-                    header
-                        .iter()
-                        .position(|n2| *n2 == col)
-                        .unwrap_or_else(|| panic!("Label column '{}' not found.", col)),
-                ),
-                Some(Vec::new()),
-            ),
-            None => (None, None),
+        // get label columns
+        let label_indices: Vec<_> = label_options
+            .columns
+            .iter()
+            .map(|col| {
+                header
+                    .iter()
+                    .position(|n2| *n2 == col)
+                    .unwrap_or_else(|| panic!("Label column '{}' not found.", col))
+            })
+            .collect();
+        let mut labels = if label_indices.is_empty() {
+            None
+        } else {
+            Some(Vec::new())
         };
 
         // transform to SOM training data format
         let mut df = DataFrame::empty(&colnames.iter().map(|x| &**x).collect::<Vec<_>>());
         let mut row = vec![0.0; colnames.len()];
 
-        reader.seek(start_pos).unwrap();
+        reader
+            .seek(start_pos)
+            .map_err(|err| Self::read_error(path, err))?;
         for (rec_idx, record) in reader.records().enumerate() {
-            let rec = record?;
+            let rec = record.map_err(|err| Self::read_error(path, err))?;
             for col in &mut row {
                 *col = 0.0;
             }
@@ -375,21 +984,39 @@ impl Processor {
                 let id = rec.get(*col_idx).unwrap();
                 id_values[idx].push(id.to_string());
             }
-            if let Some(col_idx) = &label_index {
-                let mut id = rec.get(*col_idx).unwrap();
-                if let Some(len) = label_length {
-                    if id.len() > len {
-                        id = &id[..len];
+            if let Some(labs) = labels.as_mut() {
+                let mut id = label_indices
+                    .iter()
+                    .map(|col_idx| rec.get(*col_idx).unwrap())
+                    .collect::<Vec<_>>()
+                    .join(&label_options.separator);
+                if let Some(len) = label_options.length {
+                    if id.chars().count() > len {
+                        id = id.chars().take(len).collect();
                     }
                 }
-                labels.as_mut().unwrap().push((rec_idx, id.to_string()));
+                labs.push((rec_idx, id));
             }
             let mut start = 0;
             for (layer_index, (inp, lay)) in input_layers.iter().zip(layers.iter()).enumerate() {
                 let indices = inp.indices.as_ref().unwrap();
-                if inp.is_class {
+                if inp.encodes_levels() {
+                    let (one, zero) = if inp.centered {
+                        (0.5, -0.5)
+                    } else {
+                        (1.0, 0.0)
+                    };
                     let v = rec.get(indices[0]).unwrap();
-                    if v == no_data {
+                    if inp.level_index {
+                        row[start] = if v == no_data {
+                            std::f64::NAN
+                        } else {
+                            cat_levels[layer_index]
+                                .iter()
+                                .position(|v2| v == v2)
+                                .unwrap() as f64
+                        };
+                    } else if v == no_data {
                         for col in row
                             .iter_mut()
                             .skip(start)
@@ -402,7 +1029,14 @@ impl Processor {
                             .iter()
                             .position(|v2| v == v2)
                             .unwrap();
-                        row[start + pos] = 1.0;
+                        for col in row
+                            .iter_mut()
+                            .skip(start)
+                            .take(cat_levels[layer_index].len())
+                        {
+                            *col = zero;
+                        }
+                        row[start + pos] = one;
                     }
                 } else {
                     for (i, idx) in inp.indices.as_ref().unwrap().iter().enumerate() {
@@ -426,8 +1060,11 @@ impl Processor {
         }
 
         // reduce label samples
-        let mut rng = rand::thread_rng();
-        if let Some(count) = &label_samples {
+        let mut rng = match label_options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        if let Some(count) = &label_options.samples {
             if let Some(labs) = &labels {
                 if count < &labs.len() {
                     labels = Some(rand::seq::sample_slice(&mut rng, &labs, *count));
@@ -443,24 +1080,62 @@ impl Processor {
                 scale.push(inp.scale);
             }
         }
-        let (data_norm, denorm) = norm::normalize(&df, &norm, &scale);
+        let (data_norm, mut denorm) = norm::normalize(&df, &norm, &scale);
+
+        // set whitening from standard deviations of normalized data
+        if input_layers.iter().any(|inp| inp.whiten) {
+            let std_devs = data_norm.std_devs();
+            let mut start = 0;
+            for (inp, lay) in input_layers.iter().zip(layers.iter_mut()) {
+                let ncols = inp.num_columns.unwrap();
+                if inp.whiten {
+                    *lay = lay
+                        .clone()
+                        .with_whitening(&std_devs[start..(start + ncols)]);
+                }
+                start += ncols;
+            }
+        }
+
+        // shift centered categorical layers back to 0 / 1 in de-normalization
+        let mut start = 0;
+        for inp in input_layers.iter() {
+            let ncols = inp.num_columns.unwrap();
+            if inp.centered {
+                for de in &mut denorm[start..(start + ncols)] {
+                    *de = norm::LinearTransform::new(de.scale(), de.offset() + 0.5);
+                }
+            }
+            start += ncols;
+        }
 
         Ok(Processor {
             input_layers,
             data: data_norm,
             preserve_columns,
             preserved: id_values,
-            label_column,
+            label_columns: label_options.columns,
             labels,
             layers,
             norm,
             denorm,
             scale,
             csv_options: csv_options.clone(),
+            class_probabilities: false,
+            max_distance_matrix_size: DEFAULT_MAX_DISTANCE_MATRIX_SIZE,
+            source: None,
         })
     }
 
+    fn default_max_distance_matrix_size() -> usize {
+        DEFAULT_MAX_DISTANCE_MATRIX_SIZE
+    }
+
     /// Creates an SOM for the `Processor`'s layer definitions and data.
+    ///
+    /// Weights are initialized within the ranges of the data.
+    /// If a `seed` is given, weight initialization and training are reproducible.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_som(
         &self,
         nrows: usize,
@@ -470,6 +1145,7 @@ impl Processor {
         alpha: DecayParam,
         radius: DecayParam,
         decay: DecayParam,
+        seed: Option<u64>,
     ) -> Som {
         let params = SomParams::xyf(
             epochs,
@@ -478,9 +1154,38 @@ impl Processor {
             radius,
             decay,
             self.layers.to_vec(),
-        );
+        )
+        .with_seed(seed);
+
+        let mut som = Som::new(&self.data.columns_ref_vec(), nrows, ncols, params);
+        som.init_weights_in_ranges(&self.data.ranges());
+        som
+    }
 
-        Som::new(&self.data.columns_ref_vec(), nrows, ncols, params)
+    /// Creates and fully trains an SOM for each of the given configurations, using the `Processor`'s data.
+    ///
+    /// With feature `parallel`, SOMs are trained in parallel.
+    pub fn create_soms(&self, configs: &[SomConfig]) -> Vec<Som> {
+        #[cfg(feature = "parallel")]
+        let iter = configs.par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let iter = configs.iter();
+
+        iter.map(|conf| {
+            let mut som = self.create_som(
+                conf.nrows,
+                conf.ncols,
+                conf.epochs,
+                conf.neighborhood.clone(),
+                conf.alpha.clone(),
+                conf.radius.clone(),
+                conf.decay.clone(),
+                conf.seed,
+            );
+            while let Some(()) = som.epoch(&self.data, None) {}
+            som
+        })
+        .collect()
     }
 
     /// Transforms a categorical / class layer to a vector of class labels.
@@ -498,14 +1203,13 @@ impl Processor {
                 layer_index
             )));
         }
-        let layer = &self.layers[layer_index];
-        let start_col = som.params().start_columns()[layer_index];
+        let range = som.params().column_range(layer_index);
 
-        let classes: Vec<_> = som.weights().columns()[start_col..(start_col + layer.ncols())]
+        let classes: Vec<_> = som.weights().columns()[range.clone()]
             .iter()
             .map(|n| n.splitn(2, ':').nth(1).unwrap())
             .collect();
-        let name = self.data.columns()[start_col]
+        let name = self.data.columns()[range.start]
             .splitn(2, ':')
             .next()
             .unwrap();
@@ -517,7 +1221,7 @@ impl Processor {
                 let mut v_max = std::f64::MIN;
                 let mut idx_max = 0;
                 let mut any = false;
-                for (i, &v) in row.iter().enumerate().skip(start_col).take(layer.ncols()) {
+                for (i, &v) in row.iter().enumerate().take(range.end).skip(range.start) {
                     if !v.is_nan() {
                         if v > v_max {
                             v_max = v;
@@ -527,7 +1231,7 @@ impl Processor {
                     }
                 }
                 if any {
-                    classes[idx_max - start_col].to_string()
+                    classes[idx_max - range.start].to_string()
                 } else {
                     no_data.clone()
                 }
@@ -537,37 +1241,265 @@ impl Processor {
         Ok((name.to_string(), result))
     }
 
-    /// De-normalizes a SOM layer.
-    pub fn to_denormalized(
+    /// Calculates the majority class of the rows in `data` assigned to each unit, as a grid of nrows × ncols.
+    /// This is the data-side complement to the class map of unit weights from [`to_class`](#method.to_class).
+    ///
+    /// Units without assigned data (or only rows without a class) get the no-data value.
+    /// Ties are resolved in favour of the first class in sorted order.
+    ///
+    /// Returns an error if the layer is not categorical.
+    pub fn bmu_label_grid(
         &self,
         som: &Som,
         data: &DataFrame,
         layer_index: usize,
-    ) -> Result<DataFrame, DataTypeError> {
-        let layer = &self.layers[layer_index];
-        let start_col = som.params().start_columns()[layer_index];
-        let range = start_col..(start_col + layer.ncols());
-        Ok(norm::denormalize_columns(
-            data,
-            &range.collect::<Vec<_>>(),
-            &self.denorm()[start_col..(start_col + layer.ncols())],
-        ))
-    }
+    ) -> Result<Vec<Vec<String>>, DataTypeError> {
+        let (_, classes) = self.to_class(som, data, layer_index)?;
+        let levels = self.class_levels(layer_index)?;
+        let nearest = self.nearest_unit_normalized(som, data, None);
+
+        let mut counts = vec![vec![0_usize; levels.len()]; som.weights().nrows()];
+        for (class, (unit, _)) in classes.iter().zip(nearest) {
+            if let Some(level) = levels.iter().position(|l| l == class) {
+                counts[unit][level] += 1;
+            }
+        }
 
-    /// Writes normalization and de-normalization parameters to CSV file.
-    pub fn write_normalization(&self, som: &Som, path: &str) -> Result<(), Box<dyn Error>> {
-        let mut writer = WriterBuilder::new()
-            .delimiter(self.csv_options.delimiter)
-            .from_path(path)?;
+        let (nrows, ncols) = som.size();
+        let mut grid = vec![vec![self.csv_options.no_data.clone(); ncols]; nrows];
+        for (unit, cnt) in counts.iter().enumerate() {
+            let (r, c) = som.to_row_col(unit);
+            let mut idx_max = 0;
+            for (i, count) in cnt.iter().enumerate() {
+                if *count > cnt[idx_max] {
+                    idx_max = i;
+                }
+            }
+            if cnt[idx_max] > 0 {
+                grid[r][c] = levels[idx_max].clone();
+            }
+        }
+        Ok(grid)
+    }
 
-        let mut colnames: Vec<_> = som.weights().columns().to_vec();
-        colnames.insert(0, "param".to_string());
-        writer.write_record(&colnames)?;
+    /// Transforms several categorical / class layers to a vector of composite class labels, e.g. `"setosa|wet"`.
+    ///
+    /// Returns an error if any of the layers is not categorical.
+    pub fn to_class_combined(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        layer_indices: &[usize],
+    ) -> Result<(String, Vec<String>), DataTypeError> {
+        let classes = layer_indices
+            .iter()
+            .map(|idx| self.to_class(som, data, *idx))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let mut row: Vec<_> = self
-            .denorm
+        let name = classes
             .iter()
-            .map(|d| d.inverse().scale().to_string())
+            .map(|(name, _)| &name[..])
+            .collect::<Vec<_>>()
+            .join("|");
+        let result = (0..data.nrows())
+            .map(|row| {
+                classes
+                    .iter()
+                    .map(|(_, cls)| &cls[row][..])
+                    .collect::<Vec<_>>()
+                    .join("|")
+            })
+            .collect();
+
+        Ok((name, result))
+    }
+
+    /// Calculates the confusion matrix of a categorical layer, for using the SOM as a classifier.
+    ///
+    /// The predicted class of each row of `data` is the class of its nearest unit,
+    /// which is determined without the categorical layer itself. Rows without a true class are ignored.
+    ///
+    /// Returns an error if the layer is not categorical.
+    ///
+    /// # Returns
+    /// A tuple of: (class labels, matrix as `matrix[true class][predicted class]`).
+    pub fn confusion_matrix(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        layer_index: usize,
+    ) -> Result<(Vec<String>, Vec<Vec<usize>>), DataTypeError> {
+        let (_, truth) = self.to_class(som, data, layer_index)?;
+        let (_, unit_classes) = self.to_class(som, som.weights(), layer_index)?;
+
+        let classes: Vec<_> = som.weights().columns()[som.params().column_range(layer_index)]
+            .iter()
+            .map(|n| n.splitn(2, ':').nth(1).unwrap().to_string())
+            .collect();
+
+        let mut mask = vec![true; self.layers.len()];
+        mask[layer_index] = false;
+        let nearest = self.nearest_unit_normalized(som, data, Some(&mask));
+
+        let mut matrix = vec![vec![0; classes.len()]; classes.len()];
+        for (cls, (unit, _)) in truth.iter().zip(nearest) {
+            let idx_true = classes.iter().position(|c| c == cls);
+            let idx_pred = classes.iter().position(|c| c == &unit_classes[unit]);
+            if let (Some(t), Some(p)) = (idx_true, idx_pred) {
+                matrix[t][p] += 1;
+            }
+        }
+
+        Ok((classes, matrix))
+    }
+
+    /// Returns the class levels of a categorical layer, in the order of the layer's columns (i.e. sorted).
+    pub fn class_levels(&self, layer_index: usize) -> Result<Vec<String>, DataTypeError> {
+        if !self.input_layers[layer_index].is_class {
+            return Err(DataTypeError(format!(
+                "Class levels exist only for categorical layers, but layer {} is not.",
+                layer_index
+            )));
+        }
+        let start_col: usize = self.layers[..layer_index].iter().map(|l| l.ncols()).sum();
+        let ncols = self.layers[layer_index].ncols();
+        Ok(self.data.columns()[start_col..(start_col + ncols)]
+            .iter()
+            .map(|n| n.splitn(2, ':').nth(1).unwrap().to_string())
+            .collect())
+    }
+
+    /// Calculates per-row weights inversely proportional to the frequency of the row's class in a categorical layer,
+    /// for balancing imbalanced classes in training.
+    ///
+    /// Weights are scaled so that a balanced data set results in a weight of 1 for all rows.
+    /// Rows without a class get a weight of 1.
+    ///
+    /// Returns an error if the layer is not categorical.
+    pub fn class_balance_weights(&self, layer_index: usize) -> Result<Vec<f64>, DataTypeError> {
+        if !self.input_layers[layer_index].is_class {
+            return Err(DataTypeError(format!(
+                "Class weights can be derived only for categorical layers, but layer {} is not.",
+                layer_index
+            )));
+        }
+        let start_col: usize = self.layers[..layer_index].iter().map(|l| l.ncols()).sum();
+        let ncols = self.layers[layer_index].ncols();
+
+        let classes: Vec<Option<usize>> = self
+            .data
+            .iter_rows()
+            .map(|row| {
+                let mut v_max = std::f64::MIN;
+                let mut idx_max = None;
+                for (i, &v) in row[start_col..(start_col + ncols)].iter().enumerate() {
+                    if !v.is_nan() && v > v_max {
+                        v_max = v;
+                        idx_max = Some(i);
+                    }
+                }
+                idx_max
+            })
+            .collect();
+
+        let mut counts = vec![0_usize; ncols];
+        for cls in classes.iter().flatten() {
+            counts[*cls] += 1;
+        }
+        let total: usize = counts.iter().sum();
+        let present = counts.iter().filter(|c| **c > 0).count();
+
+        Ok(classes
+            .iter()
+            .map(|cls| match cls {
+                Some(idx) => total as f64 / (present * counts[*idx]) as f64,
+                None => 1.0,
+            })
+            .collect())
+    }
+
+    /// Calculates the majority class of each SOM unit, and its purity
+    /// (i.e. the fraction of the rows assigned to the unit that are of the majority class).
+    /// Rows without a class are ignored.
+    /// # Returns
+    /// A vector of (class, purity), one per unit. Units without assigned rows are reported as `("", NaN)`.
+    pub fn unit_purity(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        layer_index: usize,
+    ) -> Result<Vec<(String, f64)>, DataTypeError> {
+        let (_, truth) = self.to_class(som, data, layer_index)?;
+
+        let classes: Vec<_> = som.weights().columns()[som.params().column_range(layer_index)]
+            .iter()
+            .map(|n| n.splitn(2, ':').nth(1).unwrap().to_string())
+            .collect();
+
+        let nearest = self.nearest_unit_normalized(som, data, None);
+
+        let mut counts = vec![vec![0_usize; classes.len()]; som.weights().nrows()];
+        for (cls, (unit, _)) in truth.iter().zip(nearest) {
+            if let Some(idx) = classes.iter().position(|c| c == cls) {
+                counts[unit][idx] += 1;
+            }
+        }
+
+        Ok(counts
+            .iter()
+            .map(|cnt| {
+                let total: usize = cnt.iter().sum();
+                if total == 0 {
+                    return ("".to_string(), std::f64::NAN);
+                }
+                let mut idx_max = 0;
+                for (i, c) in cnt.iter().enumerate() {
+                    if *c > cnt[idx_max] {
+                        idx_max = i;
+                    }
+                }
+                (classes[idx_max].clone(), cnt[idx_max] as f64 / total as f64)
+            })
+            .collect())
+    }
+
+    /// De-normalizes a SOM layer.
+    pub fn to_denormalized(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        layer_index: usize,
+    ) -> Result<DataFrame, DataTypeError> {
+        let range = som.params().column_range(layer_index);
+        Ok(norm::denormalize_columns(
+            data,
+            &range.clone().collect::<Vec<_>>(),
+            &self.denorm()[range],
+        ))
+    }
+
+    /// Returns the training data in original units, by de-normalizing all columns.
+    ///
+    /// Categorical layers are returned as one-hot values of 0 and 1 (also for centered encoding).
+    /// See [`to_class`](#method.to_class) for converting them to classes.
+    pub fn data_original(&self) -> DataFrame {
+        norm::denormalize(&self.data, &self.denorm)
+    }
+
+    /// Writes normalization and de-normalization parameters to CSV file.
+    pub fn write_normalization(&self, som: &Som, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut writer = WriterBuilder::new()
+            .delimiter(self.csv_options.delimiter)
+            .from_path(path)?;
+
+        let mut colnames: Vec<_> = som.weights().columns().to_vec();
+        colnames.insert(0, "param".to_string());
+        writer.write_record(&colnames)?;
+
+        let mut row: Vec<_> = self
+            .denorm
+            .iter()
+            .map(|d| d.inverse().scale().to_string())
             .collect();
         row.insert(0, "a_norm".to_string());
         writer.write_record(&row)?;
@@ -641,8 +1573,7 @@ impl Processor {
                     let df = denorm[idx].as_ref().unwrap();
                     let df_row = df.get_row(index);
                     for i in 0..df_row.len() {
-                        let v = df_row[i];
-                        row[offset + *start_col + i] = v.to_string();
+                        row[offset + *start_col + i] = self.csv_options.format_value(df_row[i]);
                     }
                     offset_2 += df_row.len()
                 }
@@ -660,141 +1591,2145 @@ impl Processor {
         Ok(())
     }
 
-    /// Finds the nearest unit in the SOM for each row in `data`.
+    /// Writes SOM units to a GeoJSON file, as a FeatureCollection of unit-square polygons in grid coordinates (x: column, y: row).
     ///
-    /// # Returns
-    /// A vector of (unit index, distance).
-    pub fn nearest_unit(&self, som: &Som, data: &DataFrame) -> Vec<(usize, f64)> {
-        assert_eq!(som.weights().columns(), data.columns());
+    /// Properties of each unit are its index, row and column,
+    /// its de-normalized weights of non-categorical layers, and the winning class of categorical layers.
+    pub fn write_geojson(&self, som: &Som, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut classes: Vec<Option<(String, Vec<String>)>> = vec![None; self.layers.len()];
+        let mut denorm: Vec<Option<DataFrame>> = (0..self.layers.len()).map(|_| None).collect();
+        for (idx, layer) in som.params().layers().iter().enumerate() {
+            if layer.categorical() {
+                classes[idx] = Some(self.to_class(&som, som.weights(), idx).unwrap());
+            } else {
+                denorm[idx] = Some(self.to_denormalized(&som, som.weights(), idx).unwrap());
+            }
+        }
 
-        data.iter_rows()
-            .map(|row| nn::nearest_neighbor_xyf(row, som.weights(), self.layers()))
-            .collect()
-    }
+        let features: Vec<_> = (0..som.weights().nrows())
+            .map(|index| {
+                let (r, c) = som.to_row_col(index);
+                let (x, y) = (c as f64, r as f64);
+
+                let mut properties = serde_json::Map::new();
+                properties.insert("index".to_string(), index.into());
+                properties.insert("row".to_string(), r.into());
+                properties.insert("col".to_string(), c.into());
+                for (idx, layer) in som.params().layers().iter().enumerate() {
+                    if layer.categorical() {
+                        let (name, cls) = classes[idx].as_ref().unwrap();
+                        properties.insert(name.clone(), cls[index].clone().into());
+                    } else {
+                        let df = denorm[idx].as_ref().unwrap();
+                        for (name, v) in df.columns().iter().zip(df.get_row(index)) {
+                            properties.insert(name.clone(), (*v).into());
+                        }
+                    }
+                }
 
-    /// Writes `data`, amended by the nearest SOM unit index, row and column, to a CSV file.
-    pub fn write_data_nearest(
-        &self,
-        som: &Som,
-        data: &DataFrame,
-        path: &str,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut classes: Vec<Option<Vec<String>>> = vec![None; self.layers.len()];
-        let mut denorm: Vec<Option<DataFrame>> = (0..self.layers.len()).map(|_| None).collect();
+                serde_json::json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [x, y],
+                            [x + 1.0, y],
+                            [x + 1.0, y + 1.0],
+                            [x, y + 1.0],
+                            [x, y]
+                        ]]
+                    },
+                    "properties": properties,
+                })
+            })
+            .collect();
 
-        let nearest = self.nearest_unit(&som, data);
+        let collection = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
 
-        let mut names: Vec<String> = self.preserve_columns.clone();
-        let offset_preserved = names.len();
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &collection)?;
 
-        for (idx, layer) in som.params().layers().iter().enumerate() {
-            if layer.categorical() {
-                let (name, cl) = self.to_class(&som, data, idx).unwrap();
-                classes[idx] = Some(cl);
-                names.push(name);
+        Ok(())
+    }
+
+    /// Writes a self-contained JSON descriptor of the SOM, with everything required to reproduce
+    /// best-matching unit assignment outside of this crate.
+    ///
+    /// The descriptor contains the grid geometry, the data columns, per-layer column ranges, metrics and weights,
+    /// the class levels of categorical layers, the normalization of each column, and the (normalized) unit weights.
+    /// Raw values are normalized as `value * scale + offset`. `NaN` weights are written as `null`.
+    ///
+    /// The layer distance is calculated with the layer's metric on its columns, and multiplied by the layer weight.
+    /// Layers with whitening weight each column's contribution to the Euclidean distance.
+    /// The distance to a unit is the sum over all layers.
+    pub fn write_model_descriptor(&self, som: &Som, path: &str) -> Result<(), Box<dyn Error>> {
+        let params = som.params();
+        let columns = som.weights().columns();
+
+        let mut layers = Vec::with_capacity(params.layers().len());
+        for (idx, layer) in params.layers().iter().enumerate() {
+            let range = params.column_range(idx);
+            let name = if layer.categorical() {
+                columns[range.start]
+                    .splitn(2, ':')
+                    .next()
+                    .unwrap()
+                    .to_string()
             } else {
-                let result = self.to_denormalized(&som, data, idx).unwrap();
-                names.extend_from_slice(&result.columns());
-                denorm[idx] = Some(result);
-            }
+                self.input_layers[idx].names.join(" ")
+            };
+            layers.push(serde_json::json!({
+                "name": name,
+                "start_column": range.start,
+                "columns": range.len(),
+                "weight": layer.weight(),
+                "categorical": layer.categorical(),
+                "metric": serde_json::to_value(layer.metric())?,
+                "custom_metric": layer.has_custom_metric(),
+                "whitening_weights": layer.whitening_weights(),
+                "levels": self.class_levels(idx).ok(),
+            }));
         }
 
-        let offset = names.len();
-        names.extend_from_slice(&[
-            "som_index".to_string(),
-            "som_row".to_string(),
-            "som_col".to_string(),
-        ]);
+        let normalization: Vec<_> = columns
+            .iter()
+            .zip(&self.denorm)
+            .map(|(column, denorm)| {
+                let norm = denorm.inverse();
+                serde_json::json!({
+                    "column": column,
+                    "scale": norm.scale(),
+                    "offset": norm.offset(),
+                })
+            })
+            .collect();
 
-        let no_data = &self.csv_options.no_data;
+        let units: Vec<_> = som
+            .iter_units()
+            .map(|(index, row, col, weights)| {
+                serde_json::json!({
+                    "index": index,
+                    "row": row,
+                    "col": col,
+                    "weights": weights,
+                })
+            })
+            .collect();
+
+        let descriptor = serde_json::json!({
+            "grid": {
+                "rows": som.nrows(),
+                "cols": som.ncols(),
+                "metric": serde_json::to_value(params.grid_metric())?,
+            },
+            "columns": columns,
+            "layers": layers,
+            "normalization": normalization,
+            "units": units,
+        });
+
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &descriptor)?;
+
+        Ok(())
+    }
+
+    /// Writes the SOM grid as an edge list to a CSV file, for network visualization tools.
+    ///
+    /// Each pair of grid-adjacent units (according to `neighbors`) is written once, with columns
+    /// `source`, `target` (unit indices), `grid_dist` (distance on the grid) and `weight_dist`
+    /// (distance of the units' weights, as used for finding the best-matching unit).
+    /// Equivalent to a U-matrix, expressed as a graph.
+    pub fn write_adjacency(
+        &self,
+        som: &Som,
+        neighbors: &Neighbors,
+        path: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let offsets: &[(i32, i32)] = match neighbors {
+            Neighbors::Neighbors4 => &[(0, 1), (1, 0)],
+            Neighbors::Neighbors8 => &[(0, 1), (1, -1), (1, 0), (1, 1)],
+        };
 
         let mut writer = WriterBuilder::new()
             .delimiter(self.csv_options.delimiter)
             .from_path(path)?;
+        writer.write_record(&["source", "target", "grid_dist", "weight_dist"])?;
 
-        let mut row = vec!["".to_string(); names.len()];
-        writer.write_record(&names)?;
-        for index in 0..data.nrows() {
-            for (idx, vec) in self.preserved.iter().enumerate() {
-                row[idx] = vec[index].clone();
-            }
-            for (idx, (layer, start_col)) in som
-                .params()
-                .layers()
-                .iter()
-                .zip(som.params().start_columns())
-                .enumerate()
-            {
-                if layer.categorical() {
-                    let cls = classes[idx].as_ref().unwrap();
-                    let v = &cls[index];
-                    row[*start_col + offset_preserved] = v.clone();
-                } else {
-                    let df = denorm[idx].as_ref().unwrap();
-                    let df_row = df.get_row(index);
-                    for i in 0..df_row.len() {
-                        let v = df_row[i];
-                        row[*start_col + offset_preserved + i] = if v.is_nan() {
-                            no_data.clone()
-                        } else {
-                            v.to_string()
-                        };
-                    }
+        let params = som.params();
+        for index in 0..som.weights().nrows() {
+            let (r, c) = som.to_row_col(index);
+            for (dr, dc) in offsets {
+                let (r2, c2) = (r as i32 + dr, c as i32 + dc);
+                if let Some(index2) = som.to_index_checked(r2, c2) {
+                    let grid_dist = params
+                        .grid_metric()
+                        .distance((r as f64, c as f64), (r2 as f64, c2 as f64));
+                    let weight_dist = nn::distance_xyf(
+                        som.weights().get_row(index),
+                        som.weights().get_row(index2),
+                        params.layers(),
+                        std::f64::MAX,
+                    );
+                    writer.write_record(&[
+                        index.to_string(),
+                        index2.to_string(),
+                        self.csv_options.format_value(grid_dist),
+                        self.csv_options.format_value(weight_dist),
+                    ])?;
                 }
             }
-            let (near, _dist) = nearest[index];
-            let (r, c) = som.to_row_col(near);
-            row[offset] = near.to_string();
-            row[offset + 1] = r.to_string();
-            row[offset + 2] = c.to_string();
-
-            writer.write_record(&row)?;
         }
+        writer.flush()?;
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::calc::neighborhood::Neighborhood;
-    use crate::calc::norm::Norm;
-    use crate::map::som::DecayParam;
-    use crate::proc::{InputLayer, ProcessorBuilder};
+    /// Writes the fused distance from each row in `data` to each SOM unit to a CSV file,
+    /// as a matrix with one row per data row and one column per unit. For small problems, e.g. debugging.
+    ///
+    /// Returns an error if the number of cells exceeds the maximum set by
+    /// [`with_max_distance_matrix_size`](struct.ProcessorBuilder.html#method.with_max_distance_matrix_size).
+    pub fn write_distance_matrix(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        path: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let units = som.weights().nrows();
+        let cells = data.nrows() * units;
+        if cells > self.max_distance_matrix_size {
+            return Err(format!(
+                "Distance matrix of {} x {} cells exceeds the maximum size of {} cells",
+                data.nrows(),
+                units,
+                self.max_distance_matrix_size
+            )
+            .into());
+        }
 
-    #[test]
-    fn nearest_unit() {
-        let layers = vec![
-            InputLayer::cont_simple(&[
-                "sepal_length",
-                "sepal_width",
-                "petal_length",
-                "petal_width",
-            ]),
-            InputLayer::cat_simple("species"),
-        ];
+        let mut writer = WriterBuilder::new()
+            .delimiter(self.csv_options.delimiter)
+            .from_path(path)?;
 
-        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
-            .with_delimiter(b';')
-            .build_from_file("example_data/iris.csv")
-            .unwrap();
+        let mut header: Vec<_> = (0..units).map(|u| format!("unit_{}", u)).collect();
+        header.insert(0, "row".to_string());
+        writer.write_record(&header)?;
+
+        let params = som.params();
+        for (index, row) in data.iter_rows().enumerate() {
+            let mut record: Vec<_> = som
+                .weights()
+                .iter_rows()
+                .map(|unit| {
+                    self.csv_options.format_value(nn::distance_xyf(
+                        row,
+                        unit,
+                        params.layers(),
+                        std::f64::MAX,
+                    ))
+                })
+                .collect();
+            record.insert(0, index.to_string());
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
 
-        let som = proc.create_som(
-            16,
-            20,
-            1000,
-            Neighborhood::Gauss,
-            DecayParam::lin(0.2, 0.01),
-            DecayParam::lin(8.0, 0.5),
-            DecayParam::exp(0.2, 0.001),
+        Ok(())
+    }
+
+    /// Writes the de-normalized SOM weights to a NumPy `.npy` file,
+    /// as a 3D array of 64 bit floats with shape (nrows, ncols, ndims) in C order.
+    ///
+    /// Categorical layers are written as one-hot values, like in [`data_original`](#method.data_original).
+    pub fn write_npy(&self, som: &Som, path: &str) -> Result<(), Box<dyn Error>> {
+        let weights = norm::denormalize(som.weights(), &self.denorm);
+        let (nrows, ncols) = som.size();
+
+        let mut header = format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            nrows,
+            ncols,
+            weights.ncols()
         );
+        // magic string, version and header length take 10 bytes; the total is padded to a multiple of 64
+        let padding = 63 - (10 + header.len()) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"\x93NUMPY\x01\x00")?;
+        writer.write_all(&(header.len() as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+        for v in weights.data() {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.flush()?;
 
-        let nearest = proc.nearest_unit(&som, proc.data());
+        Ok(())
+    }
 
-        assert_eq!(nearest.len(), proc.data.nrows());
+    /// Calculates the soft membership of each row in `data` to all SOM units, as opposed to the winner-take-all
+    /// assignment of [`nearest_unit_normalized`](#method.nearest_unit_normalized).
+    ///
+    /// Memberships are a softmax of the negative fused distances, `exp(-d / temperature)`, normalized to sum to 1 per row.
+    /// Low temperatures approach the winner-take-all assignment, high temperatures approach uniform membership.
+    ///
+    /// The result has one row per data row and one column per unit, named `unit_<index>`.
+    /// Computation time and memory grow with data rows × units, so this is expensive for large data and maps.
+    pub fn soft_assignment(&self, som: &Som, data: &DataFrame, temperature: f64) -> DataFrame {
+        assert!(temperature > 0.0);
+        assert_eq!(som.weights().columns(), data.columns());
 
-        //let result = proc.write_data_nearest(&som, proc.data(), "test.csv");
+        let units = som.weights().nrows();
+        let names: Vec<_> = (0..units).map(|u| format!("unit_{}", u)).collect();
+        let mut result = DataFrame::empty(&names.iter().map(|n| &n[..]).collect::<Vec<_>>());
+
+        let params = som.params();
+        let mut memberships = vec![0.0; units];
+        for row in data.iter_rows() {
+            for (m, unit) in memberships.iter_mut().zip(som.weights().iter_rows()) {
+                *m = nn::distance_xyf(row, unit, params.layers(), std::f64::MAX);
+            }
+            // shift by the minimum distance for numerical stability
+            let min = memberships.iter().cloned().fold(std::f64::MAX, f64::min);
+            for m in memberships.iter_mut() {
+                *m = (-(*m - min) / temperature).exp();
+            }
+            let sum: f64 = memberships.iter().sum();
+            result.push_row_iter(memberships.iter().map(|m| m / sum));
+        }
+        result
     }
+
+    /// Finds the nearest unit in the SOM for each row in `data`, which must be normalized like the training data,
+    /// e.g. as returned by [`data`](#method.data). See [`nearest_unit_raw`](#method.nearest_unit_raw) for data in original units.
+    ///
+    /// If `layers_mask` is given, only layers with a mask value of `true` are used for finding the nearest unit.
+    ///
+    /// # Returns
+    /// A vector of (unit index, distance).
+    pub fn nearest_unit_normalized(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        layers_mask: Option<&[bool]>,
+    ) -> Vec<(usize, f64)> {
+        assert_eq!(som.weights().columns(), data.columns());
+
+        match layers_mask {
+            Some(mask) => data
+                .iter_rows()
+                .map(|row| nn::nearest_neighbor_xyf_masked(row, som.weights(), self.layers(), mask))
+                .collect(),
+            None => data
+                .iter_rows()
+                .map(|row| nn::nearest_neighbor_xyf(row, som.weights(), self.layers()))
+                .collect(),
+        }
+    }
+
+    /// Finds the nearest unit in the SOM for each row in `raw_data`, given in original units.
+    /// The data is normalized with the transforms of the training data first.
+    ///
+    /// Categorical layers are expected as one-hot values of 0 and 1, like in [`data_original`](#method.data_original).
+    ///
+    /// # Returns
+    /// A vector of (unit index, distance).
+    pub fn nearest_unit_raw(
+        &self,
+        som: &Som,
+        raw_data: &DataFrame,
+        layers_mask: Option<&[bool]>,
+    ) -> Vec<(usize, f64)> {
+        let norm: Vec<_> = self.denorm.iter().map(|de| de.inverse()).collect();
+        let data = norm::denormalize(raw_data, &norm);
+        self.nearest_unit_normalized(som, &data, layers_mask)
+    }
+
+    /// Finds the nearest unit in the SOM for a partially specified sample, e.g. for "what-if" queries.
+    ///
+    /// `partial` contains (column index, value) pairs, with values in original units. They are normalized
+    /// with the transforms of the training data. All other columns are treated as `NA` and ignored in the search.
+    ///
+    /// # Returns
+    /// (unit index, distance)
+    pub fn nearest_unit_partial(&self, som: &Som, partial: &[(usize, f64)]) -> (usize, f64) {
+        let mut row = vec![std::f64::NAN; self.data.ncols()];
+        for (col, value) in partial {
+            row[*col] = self.denorm[*col].inverse().transform(*value);
+        }
+        nn::nearest_neighbor_xyf(&row, som.weights(), self.layers())
+    }
+
+    /// Finds the nearest unit in the SOM for each row in `data`, which must be normalized like the training data.
+    ///
+    /// `masks` contains one column mask per row. Columns with a mask value of `false` are excluded
+    /// from the distance calculation for the respective row, like missing values.
+    ///
+    /// # Returns
+    /// A vector of (unit index, distance).
+    pub fn nearest_unit_masked(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        masks: &[Vec<bool>],
+    ) -> Vec<(usize, f64)> {
+        assert_eq!(som.weights().columns(), data.columns());
+        assert_eq!(masks.len(), data.nrows());
+
+        let mut masked = vec![0.0; data.ncols()];
+        data.iter_rows()
+            .zip(masks)
+            .map(|(row, mask)| {
+                assert_eq!(mask.len(), row.len());
+                for ((m, v), use_col) in masked.iter_mut().zip(row).zip(mask) {
+                    *m = if *use_col { *v } else { std::f64::NAN };
+                }
+                nn::nearest_neighbor_xyf(&masked, som.weights(), self.layers())
+            })
+            .collect()
+    }
+
+    /// Calculates the quantization error of the training data in original units,
+    /// i.e. the mean fused distance of rows to their best-matching units, after de-normalizing both.
+    pub fn quantization_error_original(&self, som: &Som) -> f64 {
+        let nearest = self.nearest_unit_normalized(som, &self.data, None);
+        let layers = som.params().layers();
+        let denormalize = |row: &[f64]| -> Vec<f64> {
+            row.iter()
+                .zip(&self.denorm)
+                .map(|(v, tr)| tr.transform(*v))
+                .collect()
+        };
+        let sum: f64 = self
+            .data
+            .iter_rows()
+            .zip(nearest)
+            .map(|(row, (unit, _))| {
+                nn::distance_xyf(
+                    &denormalize(row),
+                    &denormalize(som.weights().get_row(unit)),
+                    layers,
+                    std::f64::MAX,
+                )
+            })
+            .sum();
+        sum / self.data.nrows() as f64
+    }
+
+    /// Calculates the mean silhouette coefficient for `data`, using the nearest SOM unit of each row as its cluster.
+    ///
+    /// Distances are calculated with the fused distance of all layers.
+    /// As the calculation is O(n²), `sample_size` can be used to approximate the silhouette from a random sample of rows.
+    pub fn silhouette(&self, som: &Som, data: &DataFrame, sample_size: Option<usize>) -> f64 {
+        let nearest = self.nearest_unit_normalized(som, data, None);
+
+        let mut rng = rand::thread_rng();
+        let indices: Vec<usize> = match sample_size {
+            Some(size) if size < data.nrows() => {
+                rand::seq::sample_indices(&mut rng, data.nrows(), size)
+            }
+            _ => (0..data.nrows()).collect(),
+        };
+        if indices.is_empty() {
+            return std::f64::NAN;
+        }
+
+        let units = som.weights().nrows();
+        let mut dist_sums = vec![0.0; units];
+        let mut dist_counts = vec![0_usize; units];
+        let mut sum = 0.0;
+        for &i in &indices {
+            for (s, c) in dist_sums.iter_mut().zip(dist_counts.iter_mut()) {
+                *s = 0.0;
+                *c = 0;
+            }
+            let row = data.get_row(i);
+            for &j in &indices {
+                if i != j {
+                    let (unit, _) = nearest[j];
+                    dist_sums[unit] +=
+                        nn::distance_xyf(row, data.get_row(j), self.layers(), std::f64::MAX);
+                    dist_counts[unit] += 1;
+                }
+            }
+
+            // Singletons get a silhouette of 0
+            let (own_unit, _) = nearest[i];
+            if dist_counts[own_unit] == 0 {
+                continue;
+            }
+            let a = dist_sums[own_unit] / dist_counts[own_unit] as f64;
+            let mut b = std::f64::MAX;
+            for (unit, (s, c)) in dist_sums.iter().zip(&dist_counts).enumerate() {
+                if unit != own_unit && *c > 0 {
+                    let d = s / *c as f64;
+                    if d < b {
+                        b = d;
+                    }
+                }
+            }
+            // Only one cluster: silhouette of 0
+            if b == std::f64::MAX {
+                continue;
+            }
+            let max = if a > b { a } else { b };
+            if max > 0.0 {
+                sum += (b - a) / max;
+            }
+        }
+        sum / indices.len() as f64
+    }
+
+    /// Writes `data`, amended by the nearest SOM unit index, row and column, to a CSV file.
+    ///
+    /// Categorical layers are written as their class, or as the per-class values if
+    /// [`with_class_probabilities`](struct.ProcessorBuilder.html#method.with_class_probabilities) is set.
+    ///
+    /// Rows are processed and written one by one, so memory usage does not grow with the size of `data`.
+    pub fn write_data_nearest(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        path: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.write_data_rows(som, data, path, None, None)?;
+        Ok(())
+    }
+
+    /// Writes rows of `data` that fit the SOM poorly (outliers), like [`write_data_nearest`](#method.write_data_nearest).
+    ///
+    /// Only rows with a distance to their nearest unit above the threshold are written, amended by the distance.
+    /// The threshold is either an absolute distance, or a quantile of the distances of all rows.
+    /// Returns an error for a quantile outside [0, 1].
+    ///
+    /// # Returns
+    /// The number of rows written.
+    pub fn write_outliers(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        threshold: OutlierThreshold,
+        path: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        match threshold {
+            OutlierThreshold::Distance(dist) => {
+                self.write_data_rows(som, data, path, Some(dist), None)
+            }
+            OutlierThreshold::Quantile(q) => {
+                if !(0.0..=1.0).contains(&q) {
+                    return Err(format!("Outlier quantile must be in [0, 1], but is {}", q).into());
+                }
+                let nearest = self.nearest_unit_normalized(som, data, None);
+                let mut distances = DataFrame::empty(&["distance"]);
+                for (_, dist) in &nearest {
+                    distances.push_row(&[*dist]);
+                }
+                let min_distance = distances.quantiles(q)[0];
+                self.write_data_rows(som, data, path, Some(min_distance), Some(&nearest))
+            }
+        }
+    }
+
+    /// Writes `data` with nearest units. If `min_distance` is given, writes only rows with a larger distance
+    /// to their nearest unit, amended by the distance. Returns the number of rows written.
+    ///
+    /// Nearest units are calculated row by row, unless they are given as (unit index, distance) per row in `nearest`.
+    fn write_data_rows(
+        &self,
+        som: &Som,
+        data: &DataFrame,
+        path: &str,
+        min_distance: Option<f64>,
+        nearest: Option<&[(usize, f64)]>,
+    ) -> Result<usize, Box<dyn Error>> {
+        assert_eq!(som.weights().columns(), data.columns());
+        let params = som.params();
+
+        let mut names: Vec<String> = self.preserve_columns.clone();
+        // Per layer: (column range, write values, class levels if written as class)
+        let mut layers: Vec<(Range<usize>, bool, Option<Vec<&str>>)> = Vec::new();
+        for (idx, layer) in params.layers().iter().enumerate() {
+            let range = params.column_range(idx);
+            let values = self.class_probabilities || !layer.categorical();
+            if values {
+                names.extend_from_slice(&data.columns()[range.clone()]);
+            }
+            let classes = if !values {
+                if !self.input_layers[idx].is_class {
+                    return Err(format!(
+                        "Classes can be derived only for categorical layers, but layer {} is not.",
+                        idx
+                    )
+                    .into());
+                }
+                let columns = &som.weights().columns()[range.clone()];
+                names.push(columns[0].splitn(2, ':').next().unwrap().to_string());
+                Some(
+                    columns
+                        .iter()
+                        .map(|n| n.splitn(2, ':').nth(1).unwrap())
+                        .collect(),
+                )
+            } else {
+                None
+            };
+            layers.push((range, values, classes));
+        }
+
+        let offset = names.len();
+        names.extend_from_slice(&[
+            "som_index".to_string(),
+            "som_row".to_string(),
+            "som_col".to_string(),
+        ]);
+        if min_distance.is_some() {
+            names.push("distance".to_string());
+        }
+
+        let no_data = &self.csv_options.no_data;
+
+        let mut writer = WriterBuilder::new()
+            .delimiter(self.csv_options.delimiter)
+            .from_path(path)?;
+
+        let mut row = vec!["".to_string(); names.len()];
+        writer.write_record(&names)?;
+        let mut count = 0;
+        for (index, data_row) in data.iter_rows().enumerate() {
+            let (near, dist) = match nearest {
+                Some(nearest) => nearest[index],
+                None => nn::nearest_neighbor_xyf(data_row, som.weights(), self.layers()),
+            };
+            if let Some(min_dist) = min_distance {
+                if dist <= min_dist {
+                    continue;
+                }
+                row[offset + 3] = self.csv_options.format_value(dist);
+            }
+            for (idx, vec) in self.preserved.iter().enumerate() {
+                row[idx] = vec[index].clone();
+            }
+            let mut col = self.preserve_columns.len();
+            for (range, values, classes) in layers.iter() {
+                if *values {
+                    for (v, de) in data_row[range.clone()]
+                        .iter()
+                        .zip(&self.denorm[range.clone()])
+                    {
+                        row[col] = self.csv_options.format_value(de.transform(*v));
+                        col += 1;
+                    }
+                }
+                if let Some(classes) = classes {
+                    let mut v_max = std::f64::MIN;
+                    let mut idx_max = None;
+                    for (i, &v) in data_row[range.clone()].iter().enumerate() {
+                        if !v.is_nan() && v > v_max {
+                            v_max = v;
+                            idx_max = Some(i);
+                        }
+                    }
+                    row[col] = match idx_max {
+                        Some(i) => classes[i].to_string(),
+                        None => no_data.clone(),
+                    };
+                    col += 1;
+                }
+            }
+            let (r, c) = som.to_row_col(near);
+            row[offset] = near.to_string();
+            row[offset + 1] = r.to_string();
+            row[offset + 2] = c.to_string();
+
+            writer.write_record(&row)?;
+            count += 1;
+        }
+        writer.flush()?;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::calc::metric::Metric;
+    use crate::calc::neighborhood::{Neighborhood, Neighbors};
+    use crate::calc::nn;
+    use crate::calc::norm::{self, Norm};
+    use crate::data::DataFrame;
+    use crate::map::som::{DecayParam, Layer, Som};
+    use crate::proc::{InputLayer, OutlierThreshold, Processor, ProcessorBuilder, SomConfig};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rand::Rng;
+    use std::fs::File;
+    use std::io::Write;
+
+    /// Layers of the iris example data: four continuous columns and the categorical species.
+    fn iris_layers() -> Vec<InputLayer> {
+        vec![
+            InputLayer::cont_simple(&[
+                "sepal_length",
+                "sepal_width",
+                "petal_length",
+                "petal_width",
+            ]),
+            InputLayer::cat_simple("species"),
+        ]
+    }
+
+    /// Builder for the iris example data, for adding further options.
+    fn iris_builder() -> ProcessorBuilder {
+        ProcessorBuilder::new(&iris_layers(), &vec![], &None, &None, &None).with_delimiter(b';')
+    }
+
+    /// Processor for the iris example data.
+    fn iris_proc() -> Processor {
+        iris_builder()
+            .build_from_file("example_data/iris.csv")
+            .unwrap()
+    }
+
+    /// Untrained SOM for the iris example data, with a neighborhood radius decreasing from `radius` to 0.5.
+    fn iris_som(
+        proc: &Processor,
+        nrows: usize,
+        ncols: usize,
+        epochs: u32,
+        radius: f64,
+        seed: Option<u64>,
+    ) -> Som {
+        proc.create_som(
+            nrows,
+            ncols,
+            epochs,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(radius, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            seed,
+        )
+    }
+
+    #[test]
+    fn nearest_unit_normalized() {
+        let proc = iris_proc();
+
+        let som = iris_som(&proc, 16, 20, 1000, 8.0, None);
+
+        let nearest = proc.nearest_unit_normalized(&som, proc.data(), None);
+        assert_eq!(nearest.len(), proc.data.nrows());
+
+        let nearest = proc.nearest_unit_normalized(&som, proc.data(), Some(&[true, false]));
+        assert_eq!(nearest.len(), proc.data.nrows());
+
+        //let result = proc.write_data_nearest(&som, proc.data(), "test.csv");
+    }
+    #[test]
+    fn silhouette() {
+        let path = std::env::temp_dir().join("kohonen-silhouette-test.csv");
+        let mut rng = rand::thread_rng();
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x;y").unwrap();
+        for i in 0..100 {
+            let offset = if i % 2 == 0 { 0.0 } else { 10.0 };
+            writeln!(
+                file,
+                "{};{}",
+                offset + rng.gen_range(0.0, 0.1),
+                offset + rng.gen_range(0.0, 0.1)
+            )
+            .unwrap();
+        }
+        drop(file);
+
+        let layers = vec![InputLayer::cont_simple(&["x", "y"])];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+
+        let mut som = proc.create_som(
+            1,
+            2,
+            50,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.2),
+            DecayParam::lin(0.0, 0.0),
+            None,
+        );
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let sil = proc.silhouette(&som, proc.data(), None);
+        assert!(sil > 0.9);
+        let sil = proc.silhouette(&som, proc.data(), Some(40));
+        assert!(sil > 0.9);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn create_soms() {
+        let proc = iris_proc();
+
+        let configs: Vec<_> = [(4, 6, 1), (6, 8, 2), (8, 10, 3)]
+            .iter()
+            .map(|(rows, cols, seed)| {
+                SomConfig::new(
+                    *rows,
+                    *cols,
+                    20,
+                    Neighborhood::Gauss,
+                    DecayParam::lin(0.2, 0.01),
+                    DecayParam::lin(3.0, 0.5),
+                    DecayParam::exp(0.2, 0.001),
+                    Some(*seed),
+                )
+            })
+            .collect();
+
+        let soms = proc.create_soms(&configs);
+        assert_eq!(soms.len(), configs.len());
+
+        for (som, (rows, cols, seed)) in soms.iter().zip(&[(4, 6, 1), (6, 8, 2), (8, 10, 3)]) {
+            let mut serial = iris_som(&proc, *rows, *cols, 20, 3.0, Some(*seed));
+            while let Some(()) = serial.epoch(proc.data(), None) {}
+            assert_eq!(som.size(), (*rows, *cols));
+            assert_eq!(som.weights().data(), serial.weights().data());
+        }
+    }
+
+    #[test]
+    fn read_gzip() {
+        let path = std::env::temp_dir().join("kohonen-iris-test.csv.gz");
+        let content = std::fs::read("example_data/iris.csv").unwrap();
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&content).unwrap();
+        encoder.finish().unwrap();
+
+        let proc = iris_builder()
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(proc.data().nrows(), 150);
+        assert_eq!(proc.data().ncols(), 7);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn auto_weight_layers() {
+        let layers = vec![
+            InputLayer::cont_simple(&[
+                "sepal_length",
+                "sepal_width",
+                "petal_length",
+                "petal_width",
+            ])
+            .with_whitening(true),
+            InputLayer::cat_simple("species"),
+        ];
+        let mut proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        proc.auto_weight_layers();
+        assert!(proc.layers()[0].whitened());
+
+        let layers = vec![
+            InputLayer::cont(
+                &["sepal_length", "sepal_width", "petal_length", "petal_width"],
+                1.0 / 4.0,
+                Norm::Gauss,
+                None,
+            ),
+            InputLayer::cat("species", 1.0 / 3.0),
+        ];
+        let proc_manual = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let weights = proc.layer_weights();
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 0.000001);
+        for (w1, w2) in weights.iter().zip(proc_manual.layer_weights()) {
+            assert!((w1 - w2).abs() < 0.000001);
+        }
+    }
+
+    #[test]
+    fn write_geojson() {
+        let proc = iris_proc();
+
+        let som = iris_som(&proc, 4, 5, 10, 3.0, None);
+
+        let path = std::env::temp_dir().join("kohonen-units-test.geojson");
+        proc.write_geojson(&som, path.to_str().unwrap()).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["type"], "FeatureCollection");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 20);
+        assert!(features[0]["properties"]["species"].is_string());
+        assert!(features[0]["properties"]["sepal_length"].is_number());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn input_layer_names() {
+        let proc = iris_proc();
+
+        let names = proc.input_layer_names();
+        assert_eq!(names.len(), 2);
+        assert_eq!(
+            names[0],
+            (
+                false,
+                vec![
+                    "sepal_length".to_string(),
+                    "sepal_width".to_string(),
+                    "petal_length".to_string(),
+                    "petal_width".to_string(),
+                ]
+            )
+        );
+        assert_eq!(names[1], (true, vec!["species".to_string()]));
+    }
+
+    #[test]
+    fn nearest_unit_raw() {
+        let layers = vec![
+            InputLayer::cont_simple(&[
+                "sepal_length",
+                "sepal_width",
+                "petal_length",
+                "petal_width",
+            ]),
+            InputLayer::cat_simple("species").with_centered_encoding(true),
+        ];
+
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let som = proc.create_som(
+            3,
+            4,
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(3.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            Some(1),
+        );
+
+        let normalized = proc.nearest_unit_normalized(&som, proc.data(), None);
+        let raw = proc.nearest_unit_raw(&som, &proc.data_original(), None);
+        assert_eq!(normalized.len(), raw.len());
+        for ((idx_1, dist_1), (idx_2, dist_2)) in normalized.iter().zip(&raw) {
+            assert_eq!(idx_1, idx_2);
+            assert!((dist_1 - dist_2).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn write_npy() {
+        let proc = iris_proc();
+
+        let som = iris_som(&proc, 2, 3, 10, 3.0, None);
+
+        let path = std::env::temp_dir().join("kohonen-npy-test.npy");
+        let path = path.to_str().unwrap();
+        proc.write_npy(&som, path).unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..(10 + header_len)]).unwrap();
+        assert!(header.contains("'shape': (2, 3, 7)"));
+        assert!(header.ends_with('\n'));
+
+        let values: Vec<_> = bytes[(10 + header_len)..]
+            .chunks(8)
+            .map(|b| {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(b);
+                f64::from_le_bytes(buf)
+            })
+            .collect();
+        assert_eq!(values.len(), 2 * 3 * 7);
+
+        let expected = norm::denormalize(som.weights(), proc.denorm());
+        assert_eq!(&values[..7], expected.get_row(0));
+        assert_eq!(&values[(5 * 7)..], expected.get_row(5));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_distance_matrix() {
+        let proc = iris_builder()
+            .with_max_distance_matrix_size(1000)
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let som = iris_som(&proc, 2, 3, 10, 3.0, None);
+
+        let path = std::env::temp_dir().join("kohonen-distance-matrix-test.csv");
+        let path = path.to_str().unwrap();
+        proc.write_distance_matrix(&som, proc.data(), path).unwrap();
+
+        let content = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 151);
+        assert_eq!(lines[0], "row;unit_0;unit_1;unit_2;unit_3;unit_4;unit_5");
+        assert!(lines.iter().all(|l| l.split(';').count() == 7));
+
+        let large = iris_som(&proc, 3, 3, 10, 3.0, None);
+        assert!(proc
+            .write_distance_matrix(&large, proc.data(), path)
+            .is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_adjacency() {
+        let proc = iris_proc();
+
+        let som = iris_som(&proc, 4, 5, 10, 3.0, None);
+
+        let path = std::env::temp_dir().join("kohonen-adjacency-test.csv");
+        let count_edges = |neighbors: &Neighbors| {
+            proc.write_adjacency(&som, neighbors, path.to_str().unwrap())
+                .unwrap();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let mut lines = content.lines();
+            assert_eq!(lines.next().unwrap(), "source;target;grid_dist;weight_dist");
+            lines.count()
+        };
+
+        // horizontal + vertical
+        assert_eq!(count_edges(&Neighbors::Neighbors4), 4 * 4 + 3 * 5);
+        // + diagonals in both directions
+        assert_eq!(
+            count_edges(&Neighbors::Neighbors8),
+            4 * 4 + 3 * 5 + 2 * 3 * 4
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn confusion_matrix() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 6, 8, 20, 3.0, None);
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let (classes, matrix) = proc.confusion_matrix(&som, proc.data(), 1).unwrap();
+        assert_eq!(classes.len(), 3);
+        assert_eq!(matrix.len(), 3);
+        let total: usize = matrix.iter().map(|row| row.iter().sum::<usize>()).sum();
+        assert_eq!(total, proc.data().nrows());
+
+        assert!(proc.confusion_matrix(&som, proc.data(), 0).is_err());
+    }
+
+    #[test]
+    fn check_scaling() {
+        let names = ["sepal_length", "sepal_width", "petal_length", "petal_width"];
+        let layers = vec![
+            InputLayer::cont_simple(&names),
+            InputLayer::cat_simple("species"),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        assert!(proc.check_scaling().is_empty());
+
+        let layers = vec![
+            InputLayer::cont(&names, 1.0, Norm::None, None),
+            InputLayer::cat_simple("species"),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        let warnings = proc.check_scaling();
+        assert!(!warnings.is_empty());
+        assert!(warnings[0].contains("sepal_length"));
+    }
+
+    #[test]
+    fn check_layer_spreads() {
+        let path = std::env::temp_dir().join("kohonen-layer-spreads-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,y").unwrap();
+        for i in 0..20 {
+            writeln!(file, "{},{}", 100.0 + 10.0 * i as f64, i).unwrap();
+        }
+        drop(file);
+
+        let build = |norm: Norm| {
+            let layers = vec![
+                InputLayer::cont(&["x"], 1.0, norm, None),
+                InputLayer::cont(&["y"], 1.0, Norm::Gauss, None),
+            ];
+            ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+                .build_from_file(path.to_str().unwrap())
+                .unwrap()
+        };
+
+        let warnings = build(Norm::None).check_scaling();
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("Layer 0 has a much larger spread than layer 1")));
+        assert!(build(Norm::Gauss).check_scaling().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn check_scaling_index_and_constant() {
+        let path = std::env::temp_dir().join("kohonen-check-scaling-index-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,c,color").unwrap();
+        let colors = ["red", "green", "blue", "yellow", "black", "white"];
+        for i in 0..20 {
+            writeln!(file, "{},0.5,{}", i, colors[i % colors.len()]).unwrap();
+        }
+        drop(file);
+
+        let layers = vec![
+            InputLayer::cont(&["x"], 1.0, Norm::Gauss, None),
+            InputLayer::cont(&["c"], 1.0, Norm::None, None),
+            InputLayer::cat_index("color", 1.0),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(proc.data().ranges()[2], (0.0, 5.0));
+        assert!(proc.check_scaling().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn layer_scale() {
+        let names = ["sepal_length", "sepal_width", "petal_length", "petal_width"];
+        let layers = vec![
+            InputLayer::cont(&names, 1.0, Norm::Unit, None).with_scale(2.0),
+            InputLayer::cat_simple("species"),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        assert_eq!(proc.scale()[0], 2.0);
+        let ranges = proc.data().ranges();
+        for (min, max) in &ranges[0..4] {
+            assert!(min.abs() < 0.000001);
+            assert!((max - 2.0).abs() < 0.000001);
+        }
+    }
+
+    #[test]
+    fn layer_to_class_combined() {
+        let path = std::env::temp_dir().join("kohonen-two-classes-test.csv");
+        let mut rng = rand::thread_rng();
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x;y;species;habitat").unwrap();
+        for i in 0..40 {
+            writeln!(
+                file,
+                "{};{};{};{}",
+                rng.gen_range(0.0, 1.0),
+                rng.gen_range(0.0, 1.0),
+                if i % 2 == 0 { "setosa" } else { "virginica" },
+                if i % 3 == 0 { "wet" } else { "dry" },
+            )
+            .unwrap();
+        }
+        drop(file);
+
+        let layers = vec![
+            InputLayer::cont_simple(&["x", "y"]),
+            InputLayer::cat_simple("species"),
+            InputLayer::cat_simple("habitat"),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+
+        let som = proc.create_som(
+            2,
+            2,
+            1,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            None,
+        );
+
+        let (name, classes) = proc.to_class_combined(&som, proc.data(), &[1, 2]).unwrap();
+        assert_eq!(&name[..], "species|habitat");
+        assert_eq!(classes.len(), 40);
+        assert_eq!(&classes[0][..], "setosa|wet");
+        assert_eq!(&classes[1][..], "virginica|dry");
+
+        assert!(proc.to_class_combined(&som, proc.data(), &[0, 1]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unit_purity() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 6, 6, 50, 3.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let purity = proc.unit_purity(&som, proc.data(), 1).unwrap();
+        assert_eq!(purity.len(), 36);
+
+        let mut hits = 0.0;
+        let mut pure_hits = 0.0;
+        for (cls, p) in &purity {
+            if cls.is_empty() {
+                assert!(p.is_nan());
+            } else {
+                assert!(*p > 0.0 && *p <= 1.0);
+                hits += 1.0;
+                pure_hits += p;
+            }
+        }
+        assert!(pure_hits / hits > 0.9);
+
+        assert!(proc.unit_purity(&som, proc.data(), 0).is_err());
+    }
+
+    #[test]
+    fn read_fixed_width() {
+        let path = std::env::temp_dir().join("kohonen-fixed-width-test.txt");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "a     bb    class").unwrap();
+        writeln!(file, "1.5   2     x").unwrap();
+        writeln!(file, "-3    40.25 yy").unwrap();
+        writeln!(file, "NA    0     x").unwrap();
+        drop(file);
+
+        let layers = vec![
+            InputLayer::new(
+                &["a", "bb"],
+                1.0,
+                false,
+                Metric::Euclidean,
+                Norm::None,
+                None,
+            ),
+            InputLayer::cat_simple("class"),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_fixed_widths(&[6, 6, 5])
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+
+        let data = proc.data();
+        assert_eq!(data.columns(), &["a", "bb", "class:x", "class:yy"]);
+        assert_eq!(data.nrows(), 3);
+        assert_eq!(data.get_row(0), &[1.5, 2.0, 1.0, 0.0]);
+        assert_eq!(data.get_row(1), &[-3.0, 40.25, 0.0, 1.0]);
+        assert!(data.get(2, 0).is_nan());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tsv_shorthand() {
+        let path = std::env::temp_dir().join("kohonen-tsv-test.tsv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "a\tb").unwrap();
+        writeln!(file, "1\t2").unwrap();
+        drop(file);
+
+        let layers = vec![InputLayer::new(
+            &["a", "b"],
+            1.0,
+            false,
+            Metric::Euclidean,
+            Norm::None,
+            None,
+        )];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .tsv()
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(proc.data().get_row(0), &[1.0, 2.0]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn centered_encoding_single_layer() {
+        let layers = vec![InputLayer::cat_simple("species").with_centered_encoding(true)];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        assert!(proc.layers()[0].categorical());
+        assert_eq!(proc.layers()[0].metric(), &Metric::Euclidean);
+
+        let mut som = proc.create_som(
+            3,
+            3,
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            Some(1),
+        );
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        // training uses the Euclidean metric, like assignment
+        let nearest = proc.nearest_unit_normalized(&som, proc.data(), None);
+        let qe = nearest.iter().map(|(_, d)| d).sum::<f64>() / nearest.len() as f64;
+        assert_eq!(som.quantization_error(proc.data()), qe);
+
+        let (_, unit_classes) = proc.to_class(&som, som.weights(), 0).unwrap();
+        assert!(unit_classes.iter().all(|c| !c.is_empty()));
+    }
+
+    #[test]
+    fn centered_encoding() {
+        let create = |centered: bool| {
+            let layers = vec![
+                InputLayer::cont_simple(&[
+                    "sepal_length",
+                    "sepal_width",
+                    "petal_length",
+                    "petal_width",
+                ]),
+                InputLayer::cat_simple("species").with_centered_encoding(centered),
+            ];
+            ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+                .with_delimiter(b';')
+                .build_from_file("example_data/iris.csv")
+                .unwrap()
+        };
+        let proc = create(false);
+        let proc_centered = create(true);
+
+        assert_eq!(proc_centered.layers()[1].metric(), &Metric::Euclidean);
+        let start = proc_centered.layers()[0].ncols();
+        for row in proc_centered.data().iter_rows() {
+            assert_eq!(row[start..].iter().filter(|v| **v == 0.5).count(), 1);
+            assert_eq!(row[start..].iter().filter(|v| **v == -0.5).count(), 2);
+        }
+
+        let mut som = proc_centered.create_som(
+            4,
+            4,
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            None,
+        );
+        let (_, classes) = proc.to_class(&som, proc.data(), 1).unwrap();
+        let (_, classes_centered) = proc_centered
+            .to_class(&som, proc_centered.data(), 1)
+            .unwrap();
+        assert_eq!(classes, classes_centered);
+
+        let denorm = proc_centered
+            .to_denormalized(&som, proc_centered.data(), 1)
+            .unwrap();
+        for (row, row_orig) in denorm.iter_rows().zip(proc.data().iter_rows()) {
+            assert_eq!(row, &row_orig[start..]);
+        }
+
+        while let Some(()) = som.epoch(proc_centered.data(), None) {}
+        let (_, unit_classes) = proc_centered.to_class(&som, som.weights(), 1).unwrap();
+        assert!(unit_classes.iter().all(|c| !c.is_empty()));
+    }
+
+    #[test]
+    fn class_levels() {
+        let proc = iris_proc();
+
+        assert_eq!(
+            proc.class_levels(1).unwrap(),
+            vec!["setosa", "versicolor", "virginica"]
+        );
+        assert!(proc.class_levels(0).is_err());
+    }
+
+    #[test]
+    fn combined_labels() {
+        let layers = vec![InputLayer::cont_simple(&["sepal_length", "sepal_width"])];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &Some(12), &None)
+            .with_delimiter(b';')
+            .with_label_columns(&["species", "petal_length"], "/")
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let labels = proc.labels().unwrap();
+        assert_eq!(labels.len(), 150);
+        assert_eq!(labels[0], (0, "setosa/1.4".to_string()));
+        // truncated
+        assert_eq!(labels[149], (149, "virginica/5.".to_string()));
+    }
+
+    #[test]
+    fn missing_file() {
+        let layers = vec![InputLayer::cont_simple(&["x"])];
+        let result = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file("does/not/exist.csv");
+        let message = result.err().unwrap().to_string();
+        assert!(message.starts_with("Unable to open data file 'does/not/exist.csv'"));
+
+        let result = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file("does/not/exist.csv.gz");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unreadable_file() {
+        let layers = vec![InputLayer::cont_simple(&["x"])];
+        let result = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file("example_data");
+        let message = result.err().unwrap().to_string();
+        assert!(message.starts_with("Unable to read data file 'example_data'"));
+
+        let result = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_keep_source(true)
+            .build_from_file("example_data");
+        let message = result.err().unwrap().to_string();
+        assert!(message.starts_with("Unable to read data file 'example_data'"));
+    }
+
+    #[test]
+    fn seeded_label_samples() {
+        let layers = vec![InputLayer::cont_simple(&["sepal_length", "sepal_width"])];
+        let build = |seed: u64| {
+            ProcessorBuilder::new(
+                &layers,
+                &vec![],
+                &Some("species".to_string()),
+                &None,
+                &Some(10),
+            )
+            .with_delimiter(b';')
+            .with_label_seed(Some(seed))
+            .build_from_file("example_data/iris.csv")
+            .unwrap()
+        };
+
+        let proc_1 = build(1);
+        let proc_2 = build(1);
+        assert_eq!(proc_1.labels().unwrap().len(), 10);
+        assert_eq!(proc_1.labels(), proc_2.labels());
+        assert_ne!(proc_1.labels(), build(2).labels());
+    }
+
+    #[test]
+    fn class_balance_weights() {
+        let path = std::env::temp_dir().join("kohonen-class-balance-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,cls").unwrap();
+        for i in 0..10 {
+            writeln!(file, "{},common", i).unwrap();
+        }
+        writeln!(file, "10,rare").unwrap();
+        writeln!(file, "11,rare").unwrap();
+        writeln!(file, "12,NA").unwrap();
+        drop(file);
+
+        let layers = vec![
+            InputLayer::cont_simple(&["x"]),
+            InputLayer::cat_simple("cls"),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+
+        let weights = proc.class_balance_weights(1).unwrap();
+        assert_eq!(weights.len(), 13);
+        assert!(weights[..10].iter().all(|w| (w - 0.6).abs() < 1e-9));
+        assert!(weights[10..12].iter().all(|w| (w - 3.0).abs() < 1e-9));
+        assert_eq!(weights[12], 1.0);
+
+        assert!(proc.class_balance_weights(0).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn empty_categorical_layer() {
+        let path = std::env::temp_dir().join("kohonen-empty-categorical-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,cls").unwrap();
+        writeln!(file, "1,NA").unwrap();
+        writeln!(file, "2,NA").unwrap();
+        drop(file);
+
+        let layers = vec![
+            InputLayer::cont_simple(&["x"]),
+            InputLayer::cat_simple("cls"),
+        ];
+        let result = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file(path.to_str().unwrap());
+
+        let message = result.err().unwrap().to_string();
+        assert_eq!(
+            message,
+            "Categorical layer 'cls' has no levels: all values are no-data ('NA')".to_string()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn soft_assignment() {
+        let proc = iris_proc();
+
+        let som = iris_som(&proc, 3, 4, 10, 2.0, Some(1));
+
+        let soft = proc.soft_assignment(&som, proc.data(), 0.1);
+        assert_eq!(soft.nrows(), 150);
+        assert_eq!(soft.ncols(), 12);
+        assert_eq!(soft.columns()[11], "unit_11");
+
+        let nearest = proc.nearest_unit_normalized(&som, proc.data(), None);
+        for (row, (bmu, _)) in soft.iter_rows().zip(nearest) {
+            let sum: f64 = row.iter().sum();
+            assert!((sum - 1.0).abs() < 1e-9);
+            assert!(row.iter().all(|m| *m <= row[bmu]));
+        }
+    }
+
+    #[test]
+    fn bmu_label_grid() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 3, 4, 10, 2.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let grid = proc.bmu_label_grid(&som, proc.data(), 1).unwrap();
+        assert_eq!(grid.len(), 3);
+        assert!(grid.iter().all(|row| row.len() == 4));
+
+        let hits = som.hit_counts(proc.data());
+        let species = ["setosa", "versicolor", "virginica"];
+        for (unit, count) in hits.iter().enumerate() {
+            let (r, c) = som.to_row_col(unit);
+            if *count > 0 {
+                assert!(species.contains(&&grid[r][c][..]));
+            } else {
+                assert_eq!(grid[r][c], "NA");
+            }
+        }
+        assert!(grid.iter().flatten().any(|cls| cls == "setosa"));
+
+        assert!(proc.bmu_label_grid(&som, proc.data(), 0).is_err());
+    }
+
+    #[test]
+    fn nearest_unit_partial() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 4, 4, 20, 2.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        // sepal length and petal length of a typical setosa
+        let (unit, _) = proc.nearest_unit_partial(&som, &[(0, 5.0), (2, 1.4)]);
+
+        let query = [
+            proc.denorm()[0].inverse().transform(5.0),
+            proc.denorm()[2].inverse().transform(1.4),
+        ];
+        let expected = som
+            .weights()
+            .iter_rows()
+            .map(|w| (w[0] - query[0]).powi(2) + (w[2] - query[1]).powi(2))
+            .enumerate()
+            .fold((0, std::f64::MAX), |min, (idx, dist)| {
+                if dist < min.1 {
+                    (idx, dist)
+                } else {
+                    min
+                }
+            });
+        assert_eq!(unit, expected.0);
+
+        let (_, classes) = proc.to_class(&som, som.weights(), 1).unwrap();
+        assert_eq!(classes[unit], "setosa");
+    }
+
+    #[test]
+    fn nearest_unit_masked() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 4, 4, 20, 2.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        // mask all continuous columns for even rows, keep only species
+        let masks: Vec<_> = (0..proc.data().nrows())
+            .map(|i| {
+                (0..proc.data().ncols())
+                    .map(|col| i % 2 == 1 || col >= 4)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let masked = proc.nearest_unit_masked(&som, proc.data(), &masks);
+        let full = proc.nearest_unit_normalized(&som, proc.data(), None);
+        let species = proc.nearest_unit_normalized(&som, proc.data(), Some(&[false, true]));
+
+        assert_eq!(masked.len(), proc.data().nrows());
+        for (i, (unit, dist)) in masked.iter().enumerate() {
+            let expected = if i % 2 == 0 { species[i] } else { full[i] };
+            assert_eq!(*unit, expected.0);
+            assert!((dist - expected.1).abs() < 1e-9);
+        }
+        assert!(masked.iter().zip(&full).step_by(2).any(|(m, f)| m.0 != f.0));
+    }
+
+    #[test]
+    fn output_precision() {
+        let proc = iris_builder()
+            .with_precision(3)
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let mut som = iris_som(&proc, 3, 3, 5, 2.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let units = std::env::temp_dir().join("kohonen-precision-units-test.csv");
+        let units = units.to_str().unwrap();
+        proc.write_som_units(&som, units, false).unwrap();
+        let nearest = std::env::temp_dir().join("kohonen-precision-nearest-test.csv");
+        let nearest = nearest.to_str().unwrap();
+        proc.write_data_nearest(&som, proc.data(), nearest).unwrap();
+
+        // index, row, col, 4 continuous values
+        let content = std::fs::read_to_string(units).unwrap();
+        for line in content.lines().skip(1) {
+            let fields: Vec<_> = line.split(';').collect();
+            for field in &fields[3..7] {
+                assert_eq!(field.split('.').nth(1).unwrap().len(), 3);
+            }
+        }
+        // 4 continuous values, species, index, row, col
+        let content = std::fs::read_to_string(nearest).unwrap();
+        for line in content.lines().skip(1) {
+            let fields: Vec<_> = line.split(';').collect();
+            for field in &fields[0..4] {
+                assert_eq!(field.split('.').nth(1).unwrap().len(), 3);
+            }
+        }
+
+        let adjacency = std::env::temp_dir().join("kohonen-precision-adjacency-test.csv");
+        let adjacency = adjacency.to_str().unwrap();
+        proc.write_adjacency(&som, &Neighbors::Neighbors4, adjacency)
+            .unwrap();
+        // source, target, grid_dist, weight_dist
+        let content = std::fs::read_to_string(adjacency).unwrap();
+        for line in content.lines().skip(1) {
+            let fields: Vec<_> = line.split(';').collect();
+            for field in &fields[2..4] {
+                assert_eq!(field.split('.').nth(1).unwrap().len(), 3);
+            }
+        }
+
+        let matrix = std::env::temp_dir().join("kohonen-precision-matrix-test.csv");
+        let matrix = matrix.to_str().unwrap();
+        proc.write_distance_matrix(&som, proc.data(), matrix)
+            .unwrap();
+        // row, 9 units
+        let content = std::fs::read_to_string(matrix).unwrap();
+        for line in content.lines().skip(1) {
+            let fields: Vec<_> = line.split(';').collect();
+            for field in &fields[1..] {
+                assert_eq!(field.split('.').nth(1).unwrap().len(), 3);
+            }
+        }
+        std::fs::remove_file(&units).unwrap();
+        std::fs::remove_file(&nearest).unwrap();
+        std::fs::remove_file(&adjacency).unwrap();
+        std::fs::remove_file(&matrix).unwrap();
+    }
+
+    #[test]
+    fn write_model_descriptor() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 3, 4, 5, 2.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let path = std::env::temp_dir().join("kohonen-model-descriptor-test.json");
+        let path = path.to_str().unwrap();
+        proc.write_model_descriptor(&som, path).unwrap();
+
+        let content = std::fs::read_to_string(path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        assert_eq!(json["grid"]["rows"], 3);
+        assert_eq!(json["grid"]["cols"], 4);
+        assert_eq!(json["columns"].as_array().unwrap().len(), 7);
+        assert_eq!(json["normalization"].as_array().unwrap().len(), 7);
+
+        let layers = json["layers"].as_array().unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0]["columns"], 4);
+        assert_eq!(layers[0]["categorical"], false);
+        assert!(layers[0]["levels"].is_null());
+        assert_eq!(layers[1]["name"], "species");
+        assert_eq!(layers[1]["start_column"], 4);
+        assert_eq!(
+            layers[1]["levels"],
+            serde_json::json!(["setosa", "versicolor", "virginica"])
+        );
+
+        let units = json["units"].as_array().unwrap();
+        assert_eq!(units.len(), 12);
+        assert_eq!(units[5]["weights"].as_array().unwrap().len(), 7);
+        assert_eq!(
+            units[5]["weights"][2].as_f64().unwrap(),
+            *som.weights().get(5, 2)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_outliers() {
+        let proc = iris_proc();
+
+        let mut som = iris_som(&proc, 3, 3, 10, 2.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let distances: Vec<_> = proc
+            .nearest_unit_normalized(&som, proc.data(), None)
+            .iter()
+            .map(|(_, d)| *d)
+            .collect();
+        let mut sorted = distances.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let threshold = sorted[sorted.len() / 2];
+
+        let path = std::env::temp_dir().join("kohonen-outliers-test.csv");
+        let path = path.to_str().unwrap();
+        let count = proc
+            .write_outliers(
+                &som,
+                proc.data(),
+                OutlierThreshold::Distance(threshold),
+                path,
+            )
+            .unwrap();
+        let expected = distances.iter().filter(|d| **d > threshold).count();
+        assert_eq!(count, expected);
+
+        let content = std::fs::read_to_string(path).unwrap();
+        let mut lines = content.lines();
+        assert!(lines.next().unwrap().ends_with(";distance"));
+        let written: Vec<f64> = lines
+            .map(|l| l.rsplit(';').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(written.len(), expected);
+        assert!(written.iter().all(|d| *d > threshold));
+
+        let count = proc
+            .write_outliers(&som, proc.data(), OutlierThreshold::Quantile(0.9), path)
+            .unwrap();
+        assert!(count > 0 && count <= distances.len() / 10 + 1);
+
+        for q in &[-0.1, 1.5, std::f64::NAN] {
+            assert!(proc
+                .write_outliers(&som, proc.data(), OutlierThreshold::Quantile(*q), path)
+                .is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn add_layer() {
+        let layers = vec![InputLayer::cont_simple(&[
+            "sepal_length",
+            "sepal_width",
+            "petal_length",
+            "petal_width",
+        ])];
+        let mut proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .with_keep_source(true)
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        let before = proc.data().get_row(10).to_vec();
+
+        proc.add_layer(InputLayer::cat_simple("species")).unwrap();
+
+        assert_eq!(proc.layers().len(), 2);
+        assert_eq!(proc.input_layers().len(), 2);
+        assert_eq!(proc.data().ncols(), 7);
+        assert_eq!(proc.data().nrows(), 150);
+        assert_eq!(proc.norm().len(), 7);
+        assert_eq!(proc.denorm().len(), 7);
+        assert_eq!(&proc.data().columns()[4], "species:setosa");
+        assert_eq!(&proc.data().get_row(10)[..4], &before[..]);
+        assert_eq!(&proc.data().get_row(10)[4..], &[1.0, 0.0, 0.0]);
+        assert!(proc.layers()[1].categorical());
+        assert_eq!(proc.layer_weights(), vec![0.5, 0.5]);
+        assert_eq!(
+            proc.class_levels(1).unwrap(),
+            vec!["setosa", "versicolor", "virginica"]
+        );
+
+        let som = proc.create_som(
+            3,
+            3,
+            5,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            Some(1),
+        );
+        assert_eq!(som.weights().ncols(), 7);
+
+        assert!(proc.add_layer(InputLayer::cat_simple("species")).is_err());
+
+        let mut proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        assert!(proc.add_layer(InputLayer::cat_simple("species")).is_err());
+    }
+
+    #[test]
+    fn quantization_error_original() {
+        let layers = vec![InputLayer::cont(&["petal_length"], 1.0, Norm::Gauss, None)];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let mut som = proc.create_som(
+            2,
+            2,
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(1.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            Some(1),
+        );
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let qe = som.quantization_error(proc.data());
+        let qe_orig = proc.quantization_error_original(&som);
+        let scale = proc.denorm()[0].scale();
+        assert!(scale > 1.0);
+        assert!((qe_orig - qe * scale).abs() < 1e-9);
+    }
+
+    #[test]
+    fn whitening() {
+        let path = std::env::temp_dir().join("kohonen-whitening-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,y").unwrap();
+        for i in 0..20 {
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+            writeln!(file, "{},{}", sign * 10.0, sign * 0.1).unwrap();
+        }
+        drop(file);
+
+        let layers =
+            vec![InputLayer::cont(&["x", "y"], 1.0, Norm::None, None).with_whitening(true)];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+        assert!(proc.layers()[0].whitened());
+
+        // unit 0 differs in the high-variance column, unit 1 in the low-variance column
+        let units = DataFrame::from_rows(&["x", "y"], &[vec![2.0, 0.0], vec![0.0, 0.5]]);
+        let sample = [0.0, 0.0];
+
+        let plain = vec![Layer::cont(2, 1.0)];
+        assert_eq!(nn::nearest_neighbor_xyf(&sample, &units, &plain).0, 1);
+        assert_eq!(
+            nn::nearest_neighbor_xyf(&sample, &units, proc.layers()).0,
+            0
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_data_nearest_streaming() {
+        let preserve = vec!["species".to_string()];
+        let proc = ProcessorBuilder::new(&iris_layers(), &preserve, &None, &None, &None)
+            .with_delimiter(b';')
+            .with_class_probabilities(true)
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let mut som = iris_som(&proc, 4, 5, 10, 3.0, Some(1));
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        let path = std::env::temp_dir().join("kohonen-data-nearest-streaming-test.csv");
+        let path = path.to_str().unwrap();
+        proc.write_data_nearest(&som, proc.data(), path).unwrap();
+        let content = std::fs::read_to_string(path).unwrap();
+
+        // Reference: assembled from pre-computed columns
+        let data = proc.data();
+        let denorm = [
+            proc.to_denormalized(&som, data, 0).unwrap(),
+            proc.to_denormalized(&som, data, 1).unwrap(),
+        ];
+        let (_, classes) = proc.to_class(&som, data, 1).unwrap();
+        let nearest = proc.nearest_unit_normalized(&som, data, None);
+
+        let mut header = vec!["species".to_string()];
+        header.extend_from_slice(denorm[0].columns());
+        header.extend_from_slice(denorm[1].columns());
+        header.extend_from_slice(&[
+            "som_index".to_string(),
+            "som_row".to_string(),
+            "som_col".to_string(),
+        ]);
+        let mut expected = vec![header.join(";")];
+        for (index, (unit, _)) in nearest.iter().enumerate() {
+            let (r, c) = som.to_row_col(*unit);
+            let mut row = vec![classes[index].clone()];
+            row.extend(denorm[0].get_row(index).iter().map(|v| v.to_string()));
+            row.extend(denorm[1].get_row(index).iter().map(|v| v.to_string()));
+            row.extend_from_slice(&[unit.to_string(), r.to_string(), c.to_string()]);
+            expected.push(row.join(";"));
+        }
+
+        assert_eq!(content.lines().collect::<Vec<_>>(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn categorical_encoded() {
+        let path = std::env::temp_dir().join("kohonen-categorical-encoded-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,color_red,color_blue,color_green").unwrap();
+        writeln!(file, "1.0,1,0,0").unwrap();
+        writeln!(file, "2.0,0,1,0").unwrap();
+        writeln!(file, "3.0,0,0,1").unwrap();
+        writeln!(file, "4.0,NA,NA,NA").unwrap();
+        drop(file);
+
+        let layers = vec![
+            InputLayer::cont_simple(&["x"]),
+            InputLayer::cat_encoded("color_", 1.0),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file(path.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            proc.data().columns(),
+            &["x", "color:red", "color:blue", "color:green"]
+        );
+        assert!(proc.layers()[1].categorical());
+        assert_eq!(proc.layers()[1].metric(), &Metric::Tanimoto);
+        assert_eq!(proc.class_levels(1).unwrap(), vec!["red", "blue", "green"]);
+        assert_eq!(&proc.data().get_row(1)[1..], &[0.0, 1.0, 0.0]);
+        assert!(proc.data().get_row(3)[1..].iter().all(|v| v.is_nan()));
+
+        let missing = vec![InputLayer::cat_encoded("size_", 1.0)];
+        assert!(
+            ProcessorBuilder::new(&missing, &vec![], &None, &None, &None)
+                .build_from_file(path.to_str().unwrap())
+                .is_err()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn categorical_index() {
+        let path = std::env::temp_dir().join("kohonen-categorical-index-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "x,color").unwrap();
+        writeln!(file, "0.1,red").unwrap();
+        writeln!(file, "0.9,red").unwrap();
+        writeln!(file, "0.2,blue").unwrap();
+        writeln!(file, "0.8,green").unwrap();
+        writeln!(file, "0.5,blue").unwrap();
+        writeln!(file, "0.3,NA").unwrap();
+        drop(file);
+
+        let build = |color: InputLayer| {
+            let layers = vec![InputLayer::cont(&["x"], 1.0, Norm::None, None), color];
+            ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+                .build_from_file(path.to_str().unwrap())
+                .unwrap()
+        };
+        let one_hot = build(InputLayer::cat("color", 1.0));
+        let index = build(InputLayer::cat_index("color", 1.0));
+
+        assert_eq!(index.data().columns(), &["x", "color"]);
+        assert!(!index.layers()[1].categorical());
+        assert_eq!(index.layers()[1].metric(), &Metric::Match);
+        let levels: Vec<_> = index.data().iter_rows().map(|row| row[1]).collect();
+        assert_eq!(&levels[..5], &[2.0, 2.0, 0.0, 1.0, 0.0]);
+        assert!(levels[5].is_nan());
+
+        // one unit per combination of x and level, with levels blue, green, red
+        let mut one_hot_units = Vec::new();
+        let mut index_units = Vec::new();
+        for x in &[0.1, 0.9] {
+            for level in 0..3 {
+                let mut row = vec![*x, 0.0, 0.0, 0.0];
+                row[1 + level] = 1.0;
+                one_hot_units.push(row);
+                index_units.push(vec![*x, level as f64]);
+            }
+        }
+        let one_hot_units = DataFrame::from_rows(&one_hot.data().columns_ref_vec(), &one_hot_units);
+        let index_units = DataFrame::from_rows(&index.data().columns_ref_vec(), &index_units);
+
+        for (row_1, row_2) in one_hot.data().iter_rows().zip(index.data().iter_rows()) {
+            let (bmu_1, _) = nn::nearest_neighbor_xyf(row_1, &one_hot_units, one_hot.layers());
+            let (bmu_2, _) = nn::nearest_neighbor_xyf(row_2, &index_units, index.layers());
+            assert_eq!(bmu_1, bmu_2);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn single_layer_categorical_index() {
+        let layers = vec![InputLayer::cat_index("species", 1.0)];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let mut som = proc.create_som(
+            3,
+            3,
+            10,
+            Neighborhood::Gauss,
+            DecayParam::lin(0.2, 0.01),
+            DecayParam::lin(2.0, 0.5),
+            DecayParam::exp(0.2, 0.001),
+            Some(1),
+        );
+        while let Some(()) = som.epoch(proc.data(), None) {}
+
+        // training and assignment use the same (match) metric
+        let nearest = proc.nearest_unit_normalized(&som, proc.data(), None);
+        let mut counts = vec![0; 9];
+        for (unit, dist) in &nearest {
+            counts[*unit] += 1;
+            assert!(*dist == 0.0 || *dist == 1.0);
+        }
+        assert_eq!(som.hit_counts(proc.data()), counts);
+        let qe = nearest.iter().map(|(_, d)| d).sum::<f64>() / nearest.len() as f64;
+        assert_eq!(som.quantization_error(proc.data()), qe);
+    }
+
+    #[test]
+    fn data_original() {
+        let layers = vec![
+            InputLayer::cont_simple(&["sepal_length", "petal_width"]),
+            InputLayer::cat_simple("species").with_centered_encoding(true),
+        ];
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+
+        let content = std::fs::read_to_string("example_data/iris.csv").unwrap();
+        let original: Vec<f64> = content
+            .lines()
+            .skip(1)
+            .map(|line| line.split(';').next().unwrap().parse().unwrap())
+            .collect();
+
+        let data = proc.data_original();
+        assert_eq!(data.columns(), proc.data().columns());
+        for (v1, v2) in data.copy_column(0).iter().zip(&original) {
+            assert!((v1 - v2).abs() < 1e-9);
+        }
+        assert_eq!(&data.get_row(0)[2..], &[1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn duplicate_columns() {
+        let path = std::env::temp_dir().join("kohonen-duplicate-columns-test.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "a,b,a,c,c").unwrap();
+        writeln!(file, "1,2,3,4,5").unwrap();
+        drop(file);
+
+        let layers = vec![InputLayer::cont_simple(&["a", "b"])];
+        let result = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .build_from_file(path.to_str().unwrap());
+
+        let message = result.err().unwrap().to_string();
+        assert_eq!(
+            message,
+            "Duplicate column names in CSV header: 'a', 'c'".to_string()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_class_probabilities() {
+        let layers = vec![
+            InputLayer::cat_simple("species"),
+            InputLayer::cont_simple(&[
+                "sepal_length",
+                "sepal_width",
+                "petal_length",
+                "petal_width",
+            ]),
+        ];
+        let som_ncols = |proc: &Processor, file: &str| {
+            let som = proc.create_som(
+                4,
+                4,
+                10,
+                Neighborhood::Gauss,
+                DecayParam::lin(0.2, 0.01),
+                DecayParam::lin(2.0, 0.5),
+                DecayParam::exp(0.2, 0.001),
+                None,
+            );
+            let path = std::env::temp_dir().join(file);
+            proc.write_data_nearest(&som, proc.data(), path.to_str().unwrap())
+                .unwrap();
+            let content = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            content.lines().next().unwrap().split(';').count()
+        };
+
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        let ncols = som_ncols(&proc, "kohonen-classes-test.csv");
+        assert_eq!(ncols, 1 + 4 + 3);
+
+        let proc = ProcessorBuilder::new(&layers, &vec![], &None, &None, &None)
+            .with_delimiter(b';')
+            .with_class_probabilities(true)
+            .build_from_file("example_data/iris.csv")
+            .unwrap();
+        let ncols_prob = som_ncols(&proc, "kohonen-class-probabilities-test.csv");
+        // class column replaced by one column per level
+        assert_eq!(ncols_prob, ncols - 1 + proc.layers()[0].ncols());
+    }
+
     #[test]
     fn write_som() {
         let layers = vec![
@@ -820,6 +3755,7 @@ mod test {
             DecayParam::lin(0.2, 0.01),
             DecayParam::lin(8.0, 0.5),
             DecayParam::exp(0.2, 0.001),
+            None,
         );
 
         //let result = proc.write_som_units(&som, "test.csv", false);
@@ -849,6 +3785,7 @@ mod test {
             DecayParam::lin(0.2, 0.01),
             DecayParam::lin(8.0, 0.5),
             DecayParam::exp(0.2, 0.001),
+            None,
         );
         let (name, classes) = proc.to_class(&som, som.weights(), 1).unwrap();
         assert_eq!(classes.len(), som.weights().nrows());
@@ -880,6 +3817,7 @@ mod test {
             DecayParam::lin(0.2, 0.01),
             DecayParam::lin(8.0, 0.5),
             DecayParam::exp(0.2, 0.001),
+            None,
         );
         let denorm = proc.to_denormalized(&som, som.weights(), 0).unwrap();
         assert_eq!(denorm.nrows(), som.weights().nrows());
@@ -911,6 +3849,7 @@ mod test {
             DecayParam::lin(0.2, 0.01),
             DecayParam::lin(8.0, 0.5),
             DecayParam::exp(0.2, 0.001),
+            None,
         );
 
         assert_eq!(proc.data().nrows(), 150);
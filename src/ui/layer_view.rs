@@ -10,16 +10,28 @@ use easy_graph::color::style::{
 };
 use easy_graph::color::{ColorMap, LinearColorMap};
 use easy_graph::ui::drawing::IntoDrawingArea;
-use easy_graph::ui::element::Rectangle;
+use easy_graph::ui::element::{PathElement, Rectangle};
 use easy_graph::ui::window::BufferWindow;
 
+/// Color for units with `NA` values.
+const NA_COLOR: RGBColor = RGBColor(200, 200, 200);
+/// Color for grid lines between units.
+const GRID_COLOR: RGBColor = RGBColor(80, 80, 80);
+
 /// Viewer for SOMs as heatmaps.
 pub struct LayerView {
     window: BufferWindow,
     layers: Vec<usize>,
     names: Vec<String>,
     layout_columns: Option<usize>,
-    scale: Option<i32>,
+    scale: Option<(i32, i32)>,
+    square_cells: bool,
+    label_size: u32,
+    label_color: RGBColor,
+    heading_size: u32,
+    heading_color: RGBColor,
+    grid_lines: bool,
+    grid_stride: usize,
 }
 
 impl LayerView {
@@ -36,42 +48,99 @@ impl LayerView {
             names: names.iter().map(|n| n.to_string()).collect(),
             layout_columns,
             scale: None,
+            square_cells: true,
+            label_size: 10,
+            label_color: BLACK,
+            heading_size: 14,
+            heading_color: BLACK,
+            grid_lines: false,
+            grid_stride: 1,
         }
     }
+    /// Sets whether to draw grid lines between units. Default: `false`.
+    pub fn with_grid_lines(mut self, grid_lines: bool) -> Self {
+        self.grid_lines = grid_lines;
+        self
+    }
+    /// Sets the stride of grid lines, i.e. draw a line every `stride` units. Default: 1.
+    pub fn with_grid_stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.grid_stride = stride;
+        self
+    }
+    /// Sets whether units are drawn as squares, or stretched to fill the panels. Default: `true`.
+    ///
+    /// Stretched cells avoid large empty margins for non-square SOMs, but distort the map's proportions.
+    pub fn with_square_cells(mut self, square: bool) -> Self {
+        self.square_cells = square;
+        self
+    }
+    /// Sets font size and color of data labels. Default: 10, black.
+    pub fn with_label_style(mut self, size: u32, color: RGBColor) -> Self {
+        self.label_size = size;
+        self.label_color = color;
+        self
+    }
+    /// Sets font size and color of headings and legend texts. Default: 14, black.
+    pub fn with_heading_style(mut self, size: u32, color: RGBColor) -> Self {
+        self.heading_size = size;
+        self.heading_color = color;
+        self
+    }
     /// If the viewer's window is still open.
     pub fn is_open(&self) -> bool {
         self.window.is_open()
     }
 
     /// Draws the given SOM. Should be called only for the same SOM repeatedly, not for different SOMs!
+    ///
+    /// If all selected layers are categorical, draws one class map per layer. Otherwise, draws one heatmap per column.
     pub fn draw(&mut self, som: &Som, data: Option<(&DataFrame, &[(usize, String)])>) {
+        let layers = self.selected_layers(som);
         let params = som.params();
-        if (self.layers.len() == 1 && params.layers()[self.layers[0]].categorical())
-            || (self.layers.is_empty()
-                && params.layers().len() == 1
-                && params.layers()[0].categorical())
-        {
-            self.draw_classes(som, data);
+        if !layers.is_empty() && layers.iter().all(|l| params.layers()[*l].categorical()) {
+            self.draw_classes(som, &layers, data);
         } else {
             self.draw_columns(som);
         }
     }
 
-    fn draw_classes(&mut self, som: &Som, data: Option<(&DataFrame, &[(usize, String)])>) {
-        let params = som.params();
-        let layer = if self.layers.is_empty() {
-            0
+    /// Returns the indices of the selected layers, or of all layers if none are selected.
+    fn selected_layers(&self, som: &Som) -> Vec<usize> {
+        if self.layers.is_empty() {
+            (0..som.params().layers().len()).collect()
         } else {
-            self.layers[0]
-        };
-        let start_col = params.start_columns()[layer];
-        let classes: Vec<_> = self.names[start_col..(start_col + params.layers()[layer].ncols())]
+            self.layers.clone()
+        }
+    }
+
+    /// Draws class maps of categorical layers, one panel per layer.
+    fn draw_classes(
+        &mut self,
+        som: &Som,
+        layers: &[usize],
+        data: Option<(&DataFrame, &[(usize, String)])>,
+    ) {
+        let params = som.params();
+        let names = &self.names;
+        // Per panel: (layer name, class names, columns)
+        let panels: Vec<(&str, Vec<&str>, Vec<usize>)> = layers
             .iter()
-            .map(|n| n.splitn(2, ':').nth(1).unwrap())
+            .map(|layer| {
+                let range = params.column_range(*layer);
+                let mut name = "";
+                let classes = names[range.clone()]
+                    .iter()
+                    .map(|n| {
+                        let mut parts = n.splitn(2, ':');
+                        name = parts.next().unwrap();
+                        parts.next().unwrap()
+                    })
+                    .collect();
+                (name, classes, range.collect())
+            })
             .collect();
 
-        let columns = self.get_columns(som);
-
         let margin = 5_i32;
         let heading = 16_i32;
         let legend = 120_i32;
@@ -82,114 +151,147 @@ impl LayerView {
         let height = height - 2 * margin as usize;
 
         if self.layout_columns.is_none() {
-            let (cols, scale) =
-                Self::calc_layout_columns(width, height, som_rows, som_cols, 1, heading, legend);
+            let (cols, scale) = Self::calc_layout_columns(
+                width,
+                height,
+                som_rows,
+                som_cols,
+                panels.len(),
+                heading,
+                legend,
+                self.square_cells,
+            );
             self.layout_columns = Some(cols);
             self.scale = Some(scale);
         }
 
-        let scale = self.scale.unwrap();
-        let test_style =
-            TextStyle::from(("sans-serif", 14).into_font()).pos(Pos::new(HPos::Left, VPos::Top));
-        let label_style = TextStyle::from(("sans-serif", 10).into_font())
+        let layout_columns = self.layout_columns.unwrap();
+
+        let layout_rows = (panels.len() as f64 / layout_columns as f64).ceil() as usize;
+        let panel_width = width as f64 / layout_columns as f64;
+        let panel_height = height as f64 / layout_rows as f64;
+
+        let (x_scale, y_scale) = self.scale.unwrap();
+        let test_style = TextStyle::from(("sans-serif", self.heading_size).into_font())
+            .color(&self.heading_color)
+            .pos(Pos::new(HPos::Left, VPos::Top));
+        let heading_style = TextStyle::from(("sans-serif", self.heading_size).into_font())
+            .color(&self.heading_color)
+            .pos(Pos::new(HPos::Left, VPos::Bottom));
+        let label_style = TextStyle::from(("sans-serif", self.label_size).into_font())
+            .color(&self.label_color)
             .pos(Pos::new(HPos::Center, VPos::Center));
+        let grid_stride = self.effective_grid_stride();
+        let grid_lines = |x_min, y_min| {
+            Self::calc_grid_lines(
+                grid_stride,
+                x_min,
+                y_min,
+                (x_scale, y_scale),
+                som_rows,
+                som_cols,
+            )
+        };
+
+        let nearest: Option<Vec<_>> = data.map(|(data, labels)| {
+            labels
+                .iter()
+                .map(|(idx, _lab)| {
+                    nearest_neighbor_xyf(data.get_row(*idx), som.weights(), som.params().layers())
+                })
+                .collect()
+        });
 
         self.window.draw(|b| {
             let root = b.into_drawing_area();
             root.fill(&WHITE).unwrap();
 
-            let x_min = margin;
-            let y_min = margin + heading;
-
-            // Draw units
-            for (idx, row) in som.weights().iter_rows().enumerate() {
-                let (r, c) = som.to_row_col(idx);
-                let x = x_min + (c as i32 * scale);
-                let y = y_min + (r as i32 * scale);
-
-                let mut v_max = std::f64::MIN;
-                let mut idx_max = 0;
-                for (index, col) in columns.iter() {
-                    let v = row[*col];
-                    if v > v_max {
-                        v_max = v;
-                        idx_max = *index;
-                    }
-                }
+            for (index, (name, classes, columns)) in panels.iter().enumerate() {
+                let lay_row = index / layout_columns;
+                let lay_col = index % layout_columns;
+                let x_min = margin + (lay_col as f64 * panel_width) as i32;
+                let y_min = margin + heading + (lay_row as f64 * panel_height) as i32;
 
-                let color = Palette99::pick(idx_max); //color_map.get_color(v_min, v_max, v);
+                // Draw units
+                for (_, r, c, row) in som.iter_units() {
+                    let x = x_min + (c as i32 * x_scale);
+                    let y = y_min + (r as i32 * y_scale);
+
+                    let mut v_max = std::f64::MIN;
+                    let mut idx_max = 0;
+                    for (index, col) in columns.iter().enumerate() {
+                        let v = row[*col];
+                        if v > v_max {
+                            v_max = v;
+                            idx_max = index;
+                        }
+                    }
 
-                root.draw(&Rectangle::new(
-                    [(x, y), (x + scale, y + scale)],
-                    ShapeStyle::from(&color).filled(),
-                ))
-                .unwrap();
-            }
+                    let color = Palette99::pick(idx_max); //color_map.get_color(v_min, v_max, v);
 
-            // Draw outline
-            root.draw(&Rectangle::new(
-                [
-                    (x_min, y_min),
-                    (
-                        x_min + scale * som_cols as i32,
-                        y_min + scale * som_rows as i32,
-                    ),
-                ],
-                ShapeStyle::from(&BLACK),
-            ))
-            .unwrap();
-
-            // Draw labels
-            if let Some((data, labels)) = data {
-                /*let nearest: Vec<_> = data
-                .iter_rows()
-                .map(|row| nearest_neighbor_xyf(row, som.weights(), som.params().layers()))
-                .collect();*/
-                let nearest: Vec<_> = labels
-                    .iter()
-                    .map(|(idx, _lab)| {
-                        nearest_neighbor_xyf(
-                            data.get_row(*idx),
-                            som.weights(),
-                            som.params().layers(),
-                        )
-                    })
-                    .collect();
-
-                let mut total_counts = vec![0; som.weights().nrows()];
-                let mut counts = vec![0; som.weights().nrows()];
-                for (idx, _) in &nearest {
-                    total_counts[*idx] += 1;
+                    root.draw(&Rectangle::new(
+                        [(x, y), (x + x_scale, y + y_scale)],
+                        ShapeStyle::from(&color).filled(),
+                    ))
+                    .unwrap();
                 }
-                //for (data_idx, label) in labels.iter() {
-                for ((idx, _), (_data_idx, label)) in nearest.iter().zip(labels) {
-                    //let (idx, _) = nearest[*data_idx];
-                    let (r, c) = som.to_row_col(*idx);
-                    let offset = 1.0 / (total_counts[*idx] + 1) as f64;
-                    let x = x_min + (c as i32 * scale) + (0.5 * scale as f64) as i32;
-                    let y = y_min
-                        + (r as i32 * scale)
-                        + (offset * (counts[*idx] + 1) as f64 * scale as f64) as i32;
-                    root.draw_text(&label, &label_style, (x, y)).unwrap();
-
-                    counts[*idx] += 1;
+
+                // Draw grid lines
+                for line in grid_lines(x_min, y_min) {
+                    root.draw(&PathElement::new(line.to_vec(), &GRID_COLOR))
+                        .unwrap();
                 }
-            }
 
-            // Draw lagend
-            let x = x_min + som.ncols() as i32 * scale + 10;
-            for (i, class) in classes.iter().enumerate() {
-                let color = Palette99::pick(i);
+                // Draw outline
                 root.draw(&Rectangle::new(
                     [
-                        (x, y_min + i as i32 * 14),
-                        (x + 10, y_min + i as i32 * 14 + 10),
+                        (x_min, y_min),
+                        (
+                            x_min + x_scale * som_cols as i32,
+                            y_min + y_scale * som_rows as i32,
+                        ),
                     ],
-                    ShapeStyle::from(&color).filled(),
+                    ShapeStyle::from(&BLACK),
                 ))
                 .unwrap();
-                root.draw_text(class, &test_style, (x + 14, y_min + i as i32 * 14))
+                root.draw_text(name, &heading_style, (x_min, y_min - 1))
+                    .unwrap();
+
+                // Draw labels
+                if let (Some((_data, labels)), Some(nearest)) = (data, &nearest) {
+                    let mut total_counts = vec![0; som.weights().nrows()];
+                    let mut counts = vec![0; som.weights().nrows()];
+                    for (idx, _) in nearest {
+                        total_counts[*idx] += 1;
+                    }
+                    for ((idx, _), (_data_idx, label)) in nearest.iter().zip(labels) {
+                        let (r, c) = som.to_row_col(*idx);
+                        let offset = 1.0 / (total_counts[*idx] + 1) as f64;
+                        let x = x_min + (c as i32 * x_scale) + (0.5 * x_scale as f64) as i32;
+                        let y = y_min
+                            + (r as i32 * y_scale)
+                            + (offset * (counts[*idx] + 1) as f64 * y_scale as f64) as i32;
+                        root.draw_text(&label, &label_style, (x, y)).unwrap();
+
+                        counts[*idx] += 1;
+                    }
+                }
+
+                // Draw lagend
+                let x = x_min + som.ncols() as i32 * x_scale + 10;
+                for (i, class) in classes.iter().enumerate() {
+                    let color = Palette99::pick(i);
+                    root.draw(&Rectangle::new(
+                        [
+                            (x, y_min + i as i32 * 14),
+                            (x + 10, y_min + i as i32 * 14 + 10),
+                        ],
+                        ShapeStyle::from(&color).filled(),
+                    ))
                     .unwrap();
+                    root.draw_text(class, &test_style, (x + 14, y_min + i as i32 * 14))
+                        .unwrap();
+                }
             }
         });
     }
@@ -215,6 +317,7 @@ impl LayerView {
                 columns.len(),
                 heading,
                 legend,
+                self.square_cells,
             );
             self.layout_columns = Some(cols);
             self.scale = Some(scale);
@@ -226,45 +329,62 @@ impl LayerView {
         let panel_width = width as f64 / layout_columns as f64;
         let panel_height = height as f64 / layout_rows as f64;
 
-        let scale = self.scale.unwrap();
-
-        let ranges = som.weights().ranges();
+        let (x_scale, y_scale) = self.scale.unwrap();
 
         let color_map =
             LinearColorMap::new(&[&RGBColor(160, 0, 150), &RED, &YELLOW, &GREEN, &CYAN]);
         let names = &self.names;
-        let test_style =
-            TextStyle::from(("sans-serif", 14).into_font()).pos(Pos::new(HPos::Left, VPos::Bottom));
+        let test_style = TextStyle::from(("sans-serif", self.heading_size).into_font())
+            .color(&self.heading_color)
+            .pos(Pos::new(HPos::Left, VPos::Bottom));
+        let grid_stride = self.effective_grid_stride();
+        let grid_lines = |x_min, y_min| {
+            Self::calc_grid_lines(
+                grid_stride,
+                x_min,
+                y_min,
+                (x_scale, y_scale),
+                som_rows,
+                som_cols,
+            )
+        };
 
         self.window.draw(|b| {
             let root = b.into_drawing_area();
             root.fill(&WHITE).unwrap();
             for (index, col) in columns {
-                let (v_min, v_max) = ranges[col];
+                let (v_min, v_max) = som.weights().range_or(col, (0.0, 1.0));
                 let lay_row = index / layout_columns;
                 let lay_col = index % layout_columns;
                 let x_min = margin + (lay_col as f64 * panel_width) as i32;
                 let y_min = margin + heading + (lay_row as f64 * panel_height) as i32;
-                for (idx, row) in som.weights().iter_rows().enumerate() {
-                    let (r, c) = som.to_row_col(idx);
+                for (_, r, c, row) in som.iter_units() {
                     let v = row[col];
-                    let x = x_min + (c as i32 * scale);
-                    let y = y_min + (r as i32 * scale);
+                    let x = x_min + (c as i32 * x_scale);
+                    let y = y_min + (r as i32 * y_scale);
 
-                    let color = color_map.get_color(v_min, v_max, v);
+                    let color = if v.is_nan() {
+                        NA_COLOR
+                    } else {
+                        color_map.get_color(v_min, v_max, v)
+                    };
 
                     root.draw(&Rectangle::new(
-                        [(x, y), (x + scale, y + scale)],
+                        [(x, y), (x + x_scale, y + y_scale)],
                         ShapeStyle::from(&color).filled(),
                     ))
                     .unwrap();
                 }
+                for line in grid_lines(x_min, y_min) {
+                    root.draw(&PathElement::new(line.to_vec(), &GRID_COLOR))
+                        .unwrap();
+                }
                 root.draw(&Rectangle::new(
                     [
                         (x_min, y_min),
                         (
-                            x_min + scale * som_cols as i32,
-                            y_min + scale * som_rows as i32,
+                            x_min + x_scale * som_cols as i32,
+                            y_min + y_scale * som_rows as i32,
                         ),
                     ],
                     ShapeStyle::from(&BLACK),
@@ -273,13 +393,20 @@ impl LayerView {
                 root.draw_text(&names[col], &test_style, (x_min, y_min - 1))
                     .unwrap();
                 let steps = 25;
-                let total_height = scale * som.nrows() as i32 - 40;
-                let total_width = scale * som.ncols() as i32;
+                // No legend margins for flat maps (e.g. 1D chains)
+                let legend_margin = if y_scale * som.nrows() as i32 > 80 {
+                    20
+                } else {
+                    0
+                };
+                let total_height = y_scale * som.nrows() as i32 - 2 * legend_margin;
+                let total_width = x_scale * som.ncols() as i32;
                 let x = x_min + total_width;
                 for i in 0..steps {
                     let value = i as f64 / steps as f64;
                     let color = color_map.get_color(0.0, 1.0, value);
-                    let y = y_min + total_height + 20 - (total_height as f64 * value) as i32;
+                    let y =
+                        y_min + total_height + legend_margin - (total_height as f64 * value) as i32;
                     root.draw(&Rectangle::new(
                         [
                             (x + 3, y),
@@ -296,6 +423,42 @@ impl LayerView {
         });
     }
 
+    /// Returns the stride of grid lines, or `None` if disabled.
+    fn effective_grid_stride(&self) -> Option<usize> {
+        if self.grid_lines {
+            Some(self.grid_stride)
+        } else {
+            None
+        }
+    }
+    /// Calculates grid lines between units, as start and end point.
+    fn calc_grid_lines(
+        stride: Option<usize>,
+        x_min: i32,
+        y_min: i32,
+        scale: (i32, i32),
+        som_rows: usize,
+        som_cols: usize,
+    ) -> Vec<[(i32, i32); 2]> {
+        let (x_scale, y_scale) = scale;
+        let mut lines = vec![];
+        let stride = match stride {
+            Some(stride) => stride,
+            None => return lines,
+        };
+        let x_max = x_min + x_scale * som_cols as i32;
+        let y_max = y_min + y_scale * som_rows as i32;
+        for c in (stride..som_cols).step_by(stride) {
+            let x = x_min + x_scale * c as i32;
+            lines.push([(x, y_min), (x, y_max)]);
+        }
+        for r in (stride..som_rows).step_by(stride) {
+            let y = y_min + y_scale * r as i32;
+            lines.push([(x_min, y), (x_max, y)]);
+        }
+        lines
+    }
+
     /// Calculates the required columns as a vector of (index, column index).
     fn get_columns(&self, som: &Som) -> Vec<(usize, usize)> {
         let params = som.params();
@@ -317,7 +480,11 @@ impl LayerView {
         };
         columns
     }
-    /// Calculates the optimum number of layout columns.
+    /// Calculates the optimum number of layout columns, and the cell scale as (x, y).
+    ///
+    /// With `square` cells, both scales are the same. Otherwise, cells are stretched to fill the panels,
+    /// and the layout with the largest cell area is selected.
+    #[allow(clippy::too_many_arguments)]
     fn calc_layout_columns(
         width: usize,
         height: usize,
@@ -326,32 +493,26 @@ impl LayerView {
         data_columns: usize,
         heading: i32,
         legend: i32,
-    ) -> (usize, i32) {
-        if data_columns == 1 {
-            let panel_width = width as f64 - legend as f64;
-            let panel_height = height as f64 - heading as f64;
+        square: bool,
+    ) -> (usize, (i32, i32)) {
+        let scales = |cols: usize| {
+            let layout_rows = (data_columns as f64 / cols as f64).ceil() as usize;
+            let panel_width = (width as f64 / cols as f64) - legend as f64;
+            let panel_height = (height as f64 / layout_rows as f64) - heading as f64;
 
             let x_scale = panel_width / som_cols as f64;
             let y_scale = panel_height / som_rows as f64;
-            let scale = (if x_scale < y_scale { x_scale } else { y_scale }) as i32;
-
-            (1, scale)
-        } else {
-            (1..data_columns)
-                .map(|cols| {
-                    let layout_rows = (data_columns as f64 / cols as f64).ceil() as usize;
-                    let panel_width = (width as f64 / cols as f64) - legend as f64;
-                    let panel_height = (height as f64 / layout_rows as f64) - heading as f64;
-
-                    let x_scale = panel_width / som_cols as f64;
-                    let y_scale = panel_height / som_rows as f64;
-                    let scale = (if x_scale < y_scale { x_scale } else { y_scale }) as i32;
-
-                    (cols, scale)
-                })
-                .max_by(|(_col1, scale1), (_col2, scale2)| scale1.cmp(scale2))
-                .unwrap()
-        }
+            if square {
+                let scale = (if x_scale < y_scale { x_scale } else { y_scale }) as i32;
+                (scale, scale)
+            } else {
+                (x_scale as i32, y_scale as i32)
+            }
+        };
+        (1..=data_columns)
+            .map(|cols| (cols, scales(cols)))
+            .max_by_key(|(_cols, (x_scale, y_scale))| x_scale * y_scale)
+            .unwrap()
     }
 }
 
@@ -359,6 +520,7 @@ impl LayerView {
 mod test {
     use crate::calc::neighborhood::Neighborhood;
     use crate::map::som::{DecayParam, Layer, Som, SomParams};
+    use crate::ui::layer_view::LayerView;
 
     #[test]
     fn view_layer() {
@@ -384,4 +546,16 @@ mod test {
         view.draw(&som, None);
         */
     }
+
+    #[test]
+    fn layout_scales() {
+        let square = LayerView::calc_layout_columns(800, 600, 10, 40, 1, 16, 20, true);
+        assert_eq!(square, (1, (19, 19)));
+
+        let stretched = LayerView::calc_layout_columns(800, 600, 10, 40, 1, 16, 20, false);
+        assert_eq!(stretched, (1, (19, 58)));
+
+        let stretched = LayerView::calc_layout_columns(800, 600, 10, 40, 4, 16, 20, false);
+        assert_eq!(stretched, (2, (9, 28)));
+    }
 }
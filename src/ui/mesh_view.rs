@@ -0,0 +1,143 @@
+//! Viewer for SOMs as meshes in feature space.
+
+use crate::data::DataFrame;
+use crate::map::som::Som;
+use easy_graph::color::style::text_anchor::{HPos, Pos, VPos};
+use easy_graph::color::style::{IntoFont, RGBColor, TextStyle, BLACK, BLUE, WHITE};
+use easy_graph::ui::drawing::IntoDrawingArea;
+use easy_graph::ui::element::{Circle, PathElement};
+use easy_graph::ui::window::BufferWindow;
+
+/// Viewer for SOMs as a connected mesh of units in the space of two continuous columns,
+/// with data points drawn as a scatter plot.
+pub struct MeshView {
+    window: BufferWindow,
+    columns: (usize, usize),
+    names: (String, String),
+    ranges: Option<((f64, f64), (f64, f64))>,
+    mesh_color: RGBColor,
+    point_color: RGBColor,
+    heading_size: u32,
+}
+
+impl MeshView {
+    /// Creates a new viewer for the given x and y columns.
+    pub fn new(window: BufferWindow, columns: (usize, usize), names: &[&str]) -> Self {
+        MeshView {
+            window,
+            columns,
+            names: (names[columns.0].to_string(), names[columns.1].to_string()),
+            ranges: None,
+            mesh_color: BLACK,
+            point_color: BLUE,
+            heading_size: 14,
+        }
+    }
+    /// Sets fixed value ranges of the x and y axes. Default: ranges of the data at the first call to `draw`.
+    pub fn with_ranges(mut self, x: (f64, f64), y: (f64, f64)) -> Self {
+        self.ranges = Some((x, y));
+        self
+    }
+    /// Sets the colors of the SOM mesh and the data points. Default: black, blue.
+    pub fn with_colors(mut self, mesh: RGBColor, points: RGBColor) -> Self {
+        self.mesh_color = mesh;
+        self.point_color = points;
+        self
+    }
+    /// Sets font size of axis labels. Default: 14.
+    pub fn with_heading_size(mut self, size: u32) -> Self {
+        self.heading_size = size;
+        self
+    }
+    /// If the viewer's window is still open.
+    pub fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    /// Draws the given SOM, and optionally data points. Should be called only for the same SOM repeatedly, not for different SOMs!
+    ///
+    /// Panics if one of the columns belongs to a categorical layer.
+    pub fn draw(&mut self, som: &Som, data: Option<&DataFrame>) {
+        let params = som.params();
+        let (col_x, col_y) = self.columns;
+        if !params.layers().is_empty() {
+            for col in &[col_x, col_y] {
+                assert!(
+                    !params.layers()[params.layer_of_column(*col)].categorical(),
+                    "Column {} of mesh view belongs to a categorical layer",
+                    col
+                );
+            }
+        }
+
+        if self.ranges.is_none() {
+            let df = data.unwrap_or_else(|| som.weights());
+            self.ranges = Some((
+                df.range_or(col_x, (0.0, 1.0)),
+                df.range_or(col_y, (0.0, 1.0)),
+            ));
+        }
+        let ((x_min, x_max), (y_min, y_max)) = self.ranges.unwrap();
+
+        let margin = 10_i32;
+        let (width, height) = self.window.size();
+        let width = width as i32 - 2 * margin;
+        let height = height as i32 - 2 * margin;
+        let to_screen = |x: f64, y: f64| {
+            (
+                margin + (width as f64 * (x - x_min) / (x_max - x_min)) as i32,
+                margin + height - (height as f64 * (y - y_min) / (y_max - y_min)) as i32,
+            )
+        };
+
+        let mesh_color = &self.mesh_color;
+        let point_color = &self.point_color;
+        let x_style = TextStyle::from(("sans-serif", self.heading_size).into_font())
+            .color(&BLACK)
+            .pos(Pos::new(HPos::Right, VPos::Bottom));
+        let y_style = TextStyle::from(("sans-serif", self.heading_size).into_font())
+            .color(&BLACK)
+            .pos(Pos::new(HPos::Left, VPos::Top));
+        let names = &self.names;
+
+        self.window.draw(|b| {
+            let root = b.into_drawing_area();
+            root.fill(&WHITE).unwrap();
+
+            if let Some(data) = data {
+                for row in data.iter_rows() {
+                    let (x, y) = (row[col_x], row[col_y]);
+                    if !x.is_nan() && !y.is_nan() {
+                        root.draw(&Circle::new(to_screen(x, y), 1, point_color))
+                            .unwrap();
+                    }
+                }
+            }
+
+            let (nrows, ncols) = som.size();
+            for row in 0..nrows {
+                for col in 0..ncols {
+                    let wt1 = som.weights_at(row, col);
+                    let pt1 = to_screen(wt1[col_x], wt1[col_y]);
+                    if row < nrows - 1 {
+                        let wt2 = som.weights_at(row + 1, col);
+                        let pt2 = to_screen(wt2[col_x], wt2[col_y]);
+                        root.draw(&PathElement::new(vec![pt1, pt2], mesh_color))
+                            .unwrap();
+                    }
+                    if col < ncols - 1 {
+                        let wt3 = som.weights_at(row, col + 1);
+                        let pt3 = to_screen(wt3[col_x], wt3[col_y]);
+                        root.draw(&PathElement::new(vec![pt1, pt3], mesh_color))
+                            .unwrap();
+                    }
+                }
+            }
+
+            root.draw_text(&names.0, &x_style, (margin + width, margin + height))
+                .unwrap();
+            root.draw_text(&names.1, &y_style, (margin, margin))
+                .unwrap();
+        });
+    }
+}
@@ -1,4 +1,6 @@
 //! Visualization.
 
 mod layer_view;
+mod mesh_view;
 pub use layer_view::LayerView;
+pub use mesh_view::MeshView;